@@ -0,0 +1,68 @@
+//! Compares the reference interpreter, the IR interpreter and the VM
+//!
+//! `programs/primes.bf` is the long-running one, extra programs like
+//! mandelbrot can be passed as file names:
+//! `cargo bench --bench vm -- mandelbrot.bf`
+
+use std::{env, fs, io, time::Instant};
+
+use brainfuck_parser::{bytecode::Bytecode, ir, vm::Vm, BrainfuckProgram, Interpreter};
+
+/// Run `f` a few times and print the fastest run
+fn bench(name: &str, runs: usize, mut f: impl FnMut()) {
+    let fastest = (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    println!("  {name:<12} {fastest:>12.2?}");
+}
+
+fn bench_program(name: &str, source: &str, input: &[u8]) {
+    let program: BrainfuckProgram = match source.parse() {
+        Ok(program) => program,
+        Err(e) => return eprintln!("{name}: {e}"),
+    };
+    let ops = ir::lower(&program);
+    let bytecode = Bytecode::from_ops(&ops);
+    println!("{name}");
+    bench("interpreter", 5, || {
+        Interpreter::new(input, io::sink()).run(&program).unwrap()
+    });
+    bench("ir", 5, || {
+        Interpreter::new(input, io::sink()).run_ops(&ops).unwrap()
+    });
+    bench("vm", 5, || {
+        Vm::new(input, io::sink()).run(&bytecode).unwrap()
+    });
+}
+
+fn main() {
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(200);
+    bench_program(
+        "hello_world",
+        include_str!("../programs/hello_world.bf"),
+        b"",
+    );
+    bench_program(
+        "rot13",
+        include_str!("../programs/rot13.bf"),
+        text.as_bytes(),
+    );
+    bench_program(
+        "nested_loops",
+        include_str!("../programs/nested_loops.bf"),
+        b"",
+    );
+    bench_program("primes", include_str!("../programs/primes.bf"), b"");
+    // `cargo bench` passes `--bench` along, skip flags like that
+    for path in env::args().skip(1).filter(|arg| !arg.starts_with('-')) {
+        match fs::read_to_string(&path) {
+            Ok(source) => bench_program(&path, &source, b""),
+            Err(e) => eprintln!("{path}: {e}"),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+Nested loops
+Busy work for benchmarks: five nested counting loops with a copy and a
+move back in the innermost one; prints nothing
+
+++++++++[>++++++++<-]>          Cell #1 is 64
+[
+    >++++++++[                  Cell #2 is 8
+        >++++++++[              Cell #3 is 8
+            >++++++++[          Cell #4 is 8
+                >+>+<<-         Copy Cell #4 into Cells #5 and #6
+            ]
+            >[-<+>]<            Move Cell #5 back into Cell #4
+            <-
+        ]<-
+    ]<-
+]
@@ -0,0 +1,16 @@
+Nested loops with a reset
+Like nested_loops but clears Cell #4 before each count; without wrapping
+at 256 the copies would otherwise keep growing and wide cells take ages
+
+++++++++[>++++++++<-]>          Cell #1 is 64
+[
+    >++++++++[                  Cell #2 is 8
+        >++++++++[              Cell #3 is 8
+            >[-]++++++++[       Cell #4 is 8 again
+                >+>+<<-         Copy Cell #4 into Cells #5 and #6
+            ]
+            >[-<+>]<            Move Cell #5 back into Cell #4
+            <-
+        ]<-
+    ]<-
+]
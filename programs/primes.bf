@@ -0,0 +1,57 @@
+Primes
+Prints the primes below 256 one per line by trial division: every
+candidate is counted down against every smaller divisor so it runs long
+enough for benchmarks
+
+>+++++++++++++++[                       Cell #0 counts the 254 candidates
+    <+++++++++++++++++>-
+]
+<-
+>+                                      Cell #1 is the candidate n
+<[
+    >+                                  Next candidate
+    [->+>>>>>>+<<<<<<<]>>>>>>>[-<<<<<<<+>>>>>>>]<<<<<<-- Try the divisors from 2 to n minus 1
+    >+                                  Cell #3 is the divisor d
+    <[
+        >+
+        <<[->>>>>>+>+<<<<<<<]>>>>>>>[-<<<<<<<+>>>>>>>] Count n down in Cell #7
+        <<<<<[->>>>>>+<+<<<<<]>>>>>[-<<<<<+>>>>>] Cell #9 counts down to the next multiple of d
+        <[
+            ->>>>>+<<<-                 Cell #12 is n mod d so far
+            >+<[>-]>[-<                 Hit a multiple so start over
+                <<<<<<[->>>>>>+<+<<<<<]>>>>>[-<<<<<+>>>>>]>>>>[-]
+            <]<<
+        <<]
+        >>>>>>+<[>-]>[-<                d divides n
+            <<<<<<<<+
+        >>>>>>>>>>]<<
+        <<<[-]>>>[-]
+        <<<<<<<<<<-
+    ]
+    >[-]
+    >>+<[>-]>[-<                        No divisor so n is prime
+        <<<[->>>>>>>>>>>>>>+<<<<<<<+<<<<<<<]>>>>>>>[-<<<<<<<+>>>>>>>]>>>>>>>>>++++++++++>>>>++++++++++ Split n into decimal digits
+        <<<<<<[
+            ->+>-
+            >+<[>-]>[-<
+                ++++++++++<[-]>>>>+>-
+                >+<[>-]>[-<
+                    ++++++++++<[-]>>>>+
+                <]<<
+            <<]<<
+        <<]
+        >>>>>>>>>[->>+<<<<<<<<<<<<<<<<<<+>>>>>>>>>>>>>>>>]<<<<<<<<<<<<<<<<[->>>>>>>>>>>>>>>>+<<<<<<<<<<<<<<<<]
+        >>>>>>>>>>>>>>>>>>[             Print the hundreds unless they are zero
+            <<++++++++++++++++++++++++++++++++++++++++++++++++.------------------------------------------------>+
+        >[-]]
+        <<<<<<[->>>>>>+<<<<<<<<<<<<<<<<<<+>>>>>>>>>>>>]<<<<<<<<<<<<[->>>>>>>>>>>>+<<<<<<<<<<<<]>>>>>>>>>>>>>>>>>[->+<]
+        >[                              Print the tens unless they and the hundreds are zero
+            <<<<<<++++++++++++++++++++++++++++++++++++++++++++++++.------------------------------------------------
+        >>>>>>[-]]
+        <<<<<<<<<<++++++++++++++++++++++++++++++++++++++++++++++++. Print the ones
+        >>>>>>>>>>>++++++++++.          And a newline
+        <<<<<<<<<<<[-]>[-]>>>[-]>[-]>>>[-]>>>[-]
+    <<<<<<<<<<<<<<<<<<<<<]<<
+    [-]
+    <<<<-
+]
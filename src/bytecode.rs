@@ -0,0 +1,145 @@
+//! Flat bytecode with precomputed jump targets
+//!
+//! Loops of the [`Op`] IR are flattened into a pair of conditional jumps, so
+//! that the [`Vm`](crate::vm::Vm) can run a program with a single program
+//! counter instead of recursing into loop bodies.
+
+use std::ops::Index;
+
+use crate::{
+    ir::{self, Op},
+    BrainfuckProgram, MachineConfig,
+};
+
+/// A single bytecode instruction
+///
+/// Apart from the jumps these do exactly what the [`Op`] of the same name does.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instr {
+    Add {
+        offset: isize,
+        delta: i32,
+    },
+    Move(isize),
+    SetZero {
+        offset: isize,
+    },
+    AddMul {
+        offset: isize,
+        factor: i32,
+    },
+    Scan(isize),
+    Output {
+        offset: isize,
+    },
+    Input {
+        offset: isize,
+    },
+    /// Start of a loop, jumping to the target if the current cell is zero
+    JumpIfZero(usize),
+    /// End of a loop, jumping to the target if the current cell is not zero
+    JumpIfNotZero(usize),
+}
+
+/// A compiled program
+///
+/// The target of a [`Instr::JumpIfZero`] is the instruction right after its
+/// [`Instr::JumpIfNotZero`] and vice versa, so that each jump lands on the
+/// first instruction to run next.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Bytecode {
+    code: Vec<Instr>,
+}
+
+impl Bytecode {
+    /// Lower a program for the given machine with [`ir::lower_for`] and
+    /// compile it
+    pub fn compile(program: &BrainfuckProgram, config: &MachineConfig) -> Self {
+        Bytecode::from_ops(&ir::lower_for(program, config))
+    }
+
+    /// Compile already lowered operations
+    pub fn from_ops(ops: &[Op]) -> Self {
+        let mut bytecode = Bytecode::default();
+        bytecode.emit(ops);
+        bytecode
+    }
+
+    fn emit(&mut self, ops: &[Op]) {
+        for op in ops {
+            let instr = match *op {
+                Op::Add { offset, delta } => Instr::Add { offset, delta },
+                Op::Move(distance) => Instr::Move(distance),
+                Op::SetZero { offset } => Instr::SetZero { offset },
+                Op::AddMul { offset, factor } => Instr::AddMul { offset, factor },
+                Op::Scan(step) => Instr::Scan(step),
+                Op::Output { offset } => Instr::Output { offset },
+                Op::Input { offset } => Instr::Input { offset },
+                Op::Loop(ref body) => {
+                    // Emit the start with a placeholder and patch it once the
+                    // end of the loop is known
+                    let start = self.code.len();
+                    self.code.push(Instr::JumpIfZero(0));
+                    self.emit(body);
+                    self.code.push(Instr::JumpIfNotZero(start + 1));
+                    self.code[start] = Instr::JumpIfZero(self.code.len());
+                    continue;
+                }
+            };
+            self.code.push(instr);
+        }
+    }
+
+    /// Number of instructions
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Whether there are no instructions at all
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// All instructions in order
+    pub fn instructions(&self) -> &[Instr] {
+        &self.code
+    }
+}
+
+impl Index<usize> for Bytecode {
+    type Output = Instr;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.code[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump_targets() {
+        // Jumps land right after their partner
+        let program: BrainfuckProgram = ",[.[-.]>,]".parse().unwrap();
+        let bytecode = Bytecode::compile(&program, &MachineConfig::default());
+        assert_eq!(
+            bytecode.instructions(),
+            [
+                Instr::Input { offset: 0 },
+                Instr::JumpIfZero(10),
+                Instr::Output { offset: 0 },
+                Instr::JumpIfZero(7),
+                Instr::Add {
+                    offset: 0,
+                    delta: -1
+                },
+                Instr::Output { offset: 0 },
+                Instr::JumpIfNotZero(4),
+                Instr::Input { offset: 1 },
+                Instr::Move(1),
+                Instr::JumpIfNotZero(2),
+            ]
+        );
+    }
+}
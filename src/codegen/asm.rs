@@ -0,0 +1,329 @@
+//! x86-64 Linux assembly backend
+//!
+//! Generates GNU assembler source in AT&T syntax for a static executable
+//! that talks to the kernel with `read`, `write` and `exit` syscalls and
+//! needs no libc. Build it with `as -o prog.o prog.s && ld -o prog prog.o`.
+//!
+//! The tape lives in `.bss`, `%rbx` holds its address and `%r12` the data
+//! pointer, which is kept on the tape at all times.
+
+use super::{CodegenError, Writer};
+use crate::{
+    ir::{self, Op},
+    BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength,
+};
+
+/// Generate assembly behaving like `program` on the given machine
+pub fn generate(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+) -> Result<String, CodegenError> {
+    if config.overflow != Overflow::Wrap {
+        return Err(CodegenError::Unsupported(
+            "overflow policies other than wrapping",
+        ));
+    }
+    let TapeLength::Fixed(len) = config.tape_length else {
+        return Err(CodegenError::Unsupported("growing tapes"));
+    };
+    if i32::try_from(len).is_err() {
+        return Err(CodegenError::Unsupported("tapes longer than 2^31 cells"));
+    }
+
+    let mut w = Writer::new("    ");
+    let size = config.cell_width.bits() / 8;
+    w.line("# Generated by brainfuck-parser");
+    w.indent();
+    w.line(".globl _start");
+    w.line("");
+    w.line(".bss");
+    w.line(".align 8");
+    w.dedent();
+    w.line("tape:");
+    w.indent();
+    w.line(format!(".zero {}", len as u64 * u64::from(size)));
+    w.dedent();
+    w.line("input_byte:");
+    w.indent();
+    w.line(".zero 1");
+    w.line("");
+    w.line(".text");
+    w.dedent();
+    w.line("_start:");
+    w.indent();
+    w.line("lea tape(%rip), %rbx");
+    w.line("xor %r12d, %r12d");
+    let mut function = Function {
+        w: &mut w,
+        config,
+        len,
+        labels: 0,
+    };
+    function.ops(&ir::lower(program));
+    w.lines(
+        "mov $60, %eax
+xor %edi, %edi
+syscall",
+    );
+    w.dedent();
+    w.lines(RUNTIME);
+    Ok(w.finish())
+}
+
+/// Subroutines shared by every program
+const RUNTIME: &str = "
+# Write the byte at (%rsi) to stdout
+write_byte:
+    mov $1, %eax
+    mov $1, %edi
+    mov $1, %edx
+    syscall
+    test %rax, %rax
+    js io_error
+    ret
+
+# Read a byte from stdin into %eax, or -1 at the end of input
+read_byte:
+    xor %eax, %eax
+    xor %edi, %edi
+    lea input_byte(%rip), %rsi
+    mov $1, %edx
+    syscall
+    test %rax, %rax
+    js io_error
+    jz 1f
+    movzbl input_byte(%rip), %eax
+    ret
+1:
+    mov $-1, %eax
+    ret
+
+off_tape:
+    lea off_tape_message(%rip), %rsi
+    mov $off_tape_length, %edx
+    jmp fail
+
+io_error:
+    lea io_error_message(%rip), %rsi
+    mov $io_error_length, %edx
+
+# Print the message at %rsi with length %edx to stderr and exit with 1
+fail:
+    mov $1, %eax
+    mov $2, %edi
+    syscall
+    mov $60, %eax
+    mov $1, %edi
+    syscall
+
+.section .rodata
+off_tape_message:
+    .ascii \"data pointer moved off the tape\\n\"
+    .set off_tape_length, . - off_tape_message
+io_error_message:
+    .ascii \"i/o error\\n\"
+    .set io_error_length, . - io_error_message";
+
+/// Writes the body of `_start`, numbering loop labels
+struct Function<'a> {
+    w: &'a mut Writer,
+    config: &'a MachineConfig,
+    len: usize,
+    labels: usize,
+}
+
+impl Function<'_> {
+    fn ops(&mut self, ops: &[Op]) {
+        for op in ops {
+            self.op(op);
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        let suffix = self.suffix();
+        match op {
+            Op::Add { offset, delta } => {
+                let cell = self.cell(*offset);
+                let delta = self.constant(i64::from(*delta));
+                self.w.line(format!("add{suffix} ${delta}, {cell}"));
+            }
+            Op::Move(distance) => self.move_by(*distance),
+            Op::SetZero { offset } => {
+                let cell = self.cell(*offset);
+                self.w.line(format!("mov{suffix} $0, {cell}"));
+            }
+            Op::AddMul { offset, factor } => {
+                let cell = self.cell(*offset);
+                let current = self.cell(0);
+                let (load, register) = match self.config.cell_width {
+                    CellWidth::U8 => ("movzbl", "%al"),
+                    CellWidth::U16 => ("movzwl", "%ax"),
+                    CellWidth::U32 | CellWidth::I32 => ("movl", "%eax"),
+                };
+                self.w.line(format!("{load} {current}, %eax"));
+                self.w.line(format!("imul ${factor}, %eax, %eax"));
+                self.w.line(format!("add{suffix} {register}, {cell}"));
+            }
+            Op::Scan(step) => {
+                let n = self.label();
+                let current = self.cell(0);
+                self.w.dedent();
+                self.w.line(format!(".Lscan{n}:"));
+                self.w.indent();
+                self.w.line(format!("cmp{suffix} $0, {current}"));
+                self.w.line(format!("je .Lscan_end{n}"));
+                self.move_by(*step);
+                self.w.line(format!("jmp .Lscan{n}"));
+                self.w.dedent();
+                self.w.line(format!(".Lscan_end{n}:"));
+                self.w.indent();
+            }
+            Op::Output { offset } => {
+                // Cells are little endian, so the cell starts with its low byte
+                let cell = self.cell(*offset);
+                self.w.line(format!("lea {cell}, %rsi"));
+                self.w.line("call write_byte");
+            }
+            Op::Input { offset } => {
+                let register = match self.config.cell_width {
+                    CellWidth::U8 => "%al",
+                    CellWidth::U16 => "%ax",
+                    CellWidth::U32 | CellWidth::I32 => "%eax",
+                };
+                // Check the cell first, the check uses %rax
+                let cell = self.cell(*offset);
+                self.w.line("call read_byte");
+                // An end of input of -1 already has all bits set for
+                // EofBehavior::MinusOne
+                match self.config.eof {
+                    EofBehavior::Unchanged => {
+                        self.w.line("test %eax, %eax");
+                        self.w.line("js 1f");
+                    }
+                    EofBehavior::Zero => {
+                        self.w.line("test %eax, %eax");
+                        self.w.line("jns 1f");
+                        self.w.line("xor %eax, %eax");
+                        self.w.dedent();
+                        self.w.line("1:");
+                        self.w.indent();
+                    }
+                    EofBehavior::MinusOne => {}
+                }
+                self.w.line(format!("mov{suffix} {register}, {cell}"));
+                if self.config.eof == EofBehavior::Unchanged {
+                    self.w.dedent();
+                    self.w.line("1:");
+                    self.w.indent();
+                }
+            }
+            Op::Loop(body) => {
+                let n = self.label();
+                let current = self.cell(0);
+                self.w.line(format!("cmp{suffix} $0, {current}"));
+                self.w.line(format!("je .Lend{n}"));
+                self.w.dedent();
+                self.w.line(format!(".Lloop{n}:"));
+                self.w.indent();
+                self.ops(body);
+                self.w.line(format!("cmp{suffix} $0, {current}"));
+                self.w.line(format!("jne .Lloop{n}"));
+                self.w.dedent();
+                self.w.line(format!(".Lend{n}:"));
+                self.w.indent();
+            }
+        }
+    }
+
+    /// A fresh number for loop labels
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    /// Instruction suffix for the cell size
+    fn suffix(&self) -> char {
+        match self.config.cell_width {
+            CellWidth::U8 => 'b',
+            CellWidth::U16 => 'w',
+            CellWidth::U32 | CellWidth::I32 => 'l',
+        }
+    }
+
+    /// Memory operand for the cell at `offset`, checking that it is on the
+    /// tape first; the pointer itself always is
+    fn cell(&mut self, offset: isize) -> String {
+        let size = self.config.cell_width.bits() / 8;
+        if offset == 0 {
+            return format!("(%rbx,%r12,{size})");
+        }
+        self.w.line(format!("lea {offset}(%r12), %rax"));
+        self.w.line(format!("cmp ${}, %rax", self.len));
+        self.w.line("jae off_tape");
+        format!("{}(%rbx,%r12,{size})", offset * size as isize)
+    }
+
+    /// Move the pointer, stopping if it leaves the tape
+    fn move_by(&mut self, distance: isize) {
+        self.w.line(format!("add ${distance}, %r12"));
+        self.w.line(format!("cmp ${}, %r12", self.len));
+        self.w.line("jae off_tape");
+    }
+
+    /// An immediate of the cell size, wrapped to its signed range
+    fn constant(&self, value: i64) -> i64 {
+        let unused = 64 - self.config.cell_width.bits();
+        (value << unused) >> unused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loop_labels() {
+        // Loops compare before entering and branch back at the end
+        let program: BrainfuckProgram = ",[.,]".parse().unwrap();
+        let generated = generate(&program, &MachineConfig::default()).unwrap();
+        assert!(generated.contains(
+            "    cmpb $0, (%rbx,%r12,1)
+    je .Lend0
+.Lloop0:
+    lea (%rbx,%r12,1), %rsi
+    call write_byte
+"
+        ));
+        assert!(generated.contains(
+            "    cmpb $0, (%rbx,%r12,1)
+    jne .Lloop0
+.Lend0:
+"
+        ));
+    }
+
+    #[test]
+    fn test_cell_width() {
+        // Instruction suffixes, scales and immediates follow the cell size
+        let program: BrainfuckProgram = "->-".parse().unwrap();
+        let config = MachineConfig {
+            cell_width: CellWidth::U16,
+            ..Default::default()
+        };
+        let generated = generate(&program, &config).unwrap();
+        assert!(generated.contains("addw $-1, (%rbx,%r12,2)"));
+        assert!(generated.contains("addw $-1, 2(%rbx,%r12,2)"));
+        assert!(generated.contains(".zero 60000"));
+    }
+
+    #[test]
+    fn test_no_libc() {
+        // Only raw syscalls, no calls into anything outside the file
+        let program: BrainfuckProgram = ",.".parse().unwrap();
+        let generated = generate(&program, &MachineConfig::default()).unwrap();
+        for line in generated.lines().filter(|l| l.trim().starts_with("call")) {
+            assert!(line.ends_with("read_byte") || line.ends_with("write_byte"));
+        }
+        assert!(!generated.contains("@PLT"));
+    }
+}
@@ -0,0 +1,269 @@
+//! C backend
+//!
+//! Generates a self-contained C99 file using `getchar`/`putchar`. All cell
+//! accesses go through a small `at` function that checks the tape bounds, or
+//! grows the tape for [`TapeLength::Growable`] and [`TapeLength::Unbounded`].
+
+use super::{CodegenError, Writer};
+use crate::{
+    ir::{self, Op},
+    BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength,
+};
+
+/// Generate a C program behaving like `program` on the given machine
+pub fn generate(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+) -> Result<String, CodegenError> {
+    let mut w = Writer::new("    ");
+    prelude(&mut w, config);
+    w.line("int main(void) {");
+    w.indent();
+    body(&mut w, &ir::lower_for(program, config));
+    w.line("return 0;");
+    w.dedent();
+    w.line("}");
+    Ok(w.finish())
+}
+
+/// C type, smallest and largest value of a cell
+fn cell_type(cell_width: CellWidth) -> (&'static str, &'static str, &'static str) {
+    match cell_width {
+        CellWidth::U8 => ("uint8_t", "0", "UINT8_MAX"),
+        CellWidth::U16 => ("uint16_t", "0", "UINT16_MAX"),
+        CellWidth::U32 => ("uint32_t", "0", "UINT32_MAX"),
+        CellWidth::I32 => ("int32_t", "INT32_MIN", "INT32_MAX"),
+    }
+}
+
+/// Includes, the tape and the helper functions used by the body
+fn prelude(w: &mut Writer, config: &MachineConfig) {
+    let (cell, min, max) = cell_type(config.cell_width);
+    w.line("/* Generated by brainfuck-parser */");
+    w.lines("#include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>");
+    w.line("");
+    w.line(format!("typedef {cell} cell;"));
+    w.line(format!("#define CELL_MIN {min}"));
+    w.line(format!("#define CELL_MAX {max}"));
+    w.line("");
+
+    match config.tape_length {
+        TapeLength::Fixed(len) => {
+            w.line(format!("#define TAPE_LEN {len}L"));
+            w.line("");
+            w.line("static cell tape[TAPE_LEN];");
+            w.line("static long p;");
+            w.line("");
+            w.line("/* The cell at position i, stopping if it is off the tape */");
+            w.line("static inline cell *at(long i) {");
+            w.indent();
+            w.line("if (i < 0 || i >= TAPE_LEN) {");
+            w.indent();
+            off_tape(w);
+            w.dedent();
+            w.line("}");
+            w.line("return &tape[i];");
+        }
+        TapeLength::Growable | TapeLength::Unbounded => {
+            w.line("static cell *tape;");
+            w.line("static long len, origin, p;");
+            w.line("");
+            w.line("/* The cell at position i, growing the tape as needed */");
+            w.line("static inline cell *at(long i) {");
+            w.indent();
+            w.line("if (i + origin < 0) {");
+            w.indent();
+            if config.tape_length == TapeLength::Growable {
+                off_tape(w);
+            } else {
+                w.lines(
+                    "long grow = -(i + origin) > len ? -(i + origin) : len;
+tape = realloc(tape, (len + grow) * sizeof(cell));
+if (!tape) {
+    perror(\"realloc\");
+    exit(1);
+}
+memmove(tape + grow, tape, len * sizeof(cell));
+memset(tape, 0, grow * sizeof(cell));
+origin += grow;
+len += grow;",
+                );
+            }
+            w.dedent();
+            w.lines(
+                "}
+if (i + origin >= len) {
+    long new_len = i + origin + 1 > 2 * len ? i + origin + 1 : 2 * len;
+    tape = realloc(tape, new_len * sizeof(cell));
+    if (!tape) {
+        perror(\"realloc\");
+        exit(1);
+    }
+    memset(tape + len, 0, (new_len - len) * sizeof(cell));
+    len = new_len;
+}
+return &tape[i + origin];",
+            );
+        }
+    }
+    w.dedent();
+    w.line("}");
+    w.line("");
+
+    w.line("static inline void add(long i, long long delta) {");
+    w.indent();
+    w.line("cell *c = at(i);");
+    match config.overflow {
+        // Unsigned arithmetic keeps exactly the low bits
+        Overflow::Wrap => {
+            w.line("*c = (cell)((unsigned long long)*c + (unsigned long long)delta);")
+        }
+        Overflow::Saturate => w.lines(
+            "long long v = (long long)*c + delta;
+*c = v < CELL_MIN ? CELL_MIN : v > CELL_MAX ? CELL_MAX : (cell)v;",
+        ),
+        Overflow::Error => w.lines(
+            "long long v = (long long)*c + delta;
+if (v < CELL_MIN || v > CELL_MAX) {
+    fprintf(stderr, \"cell %ld overflowed\\n\", i);
+    exit(1);
+}
+*c = (cell)v;",
+        ),
+    }
+    w.dedent();
+    w.line("}");
+    w.line("");
+
+    w.lines(
+        "static inline void move(long distance) {
+    p += distance;
+    at(p);
+}
+
+static inline void output(long i) {
+    putchar((unsigned char)*at(i));
+}
+
+static inline void input(long i) {
+    int c = getchar();",
+    );
+    w.indent();
+    match config.eof {
+        EofBehavior::Unchanged => w.lines("if (c != EOF) {\n    *at(i) = (cell)c;\n}"),
+        EofBehavior::Zero => w.line("*at(i) = c == EOF ? 0 : (cell)c;"),
+        EofBehavior::MinusOne => w.line("*at(i) = c == EOF ? (cell)-1 : (cell)c;"),
+    }
+    w.dedent();
+    w.line("}");
+    w.line("");
+}
+
+/// Report that the pointer left the tape and stop
+fn off_tape(w: &mut Writer) {
+    w.line("fprintf(stderr, \"data pointer moved off the tape to cell %ld\\n\", i);");
+    w.line("exit(1);");
+}
+
+/// `p` plus an offset, as a C expression
+fn position(offset: isize) -> String {
+    match offset {
+        0 => "p".to_string(),
+        offset if offset < 0 => format!("p - {}", -offset),
+        offset => format!("p + {offset}"),
+    }
+}
+
+fn body(w: &mut Writer, ops: &[Op]) {
+    for op in ops {
+        match op {
+            Op::Add { offset, delta } => w.line(format!("add({}, {delta});", position(*offset))),
+            Op::Move(distance) => w.line(format!("move({distance});")),
+            Op::SetZero { offset } => w.line(format!("*at({}) = 0;", position(*offset))),
+            Op::AddMul { offset, factor } => w.line(format!(
+                "add({}, (long long)*at(p) * {factor});",
+                position(*offset)
+            )),
+            Op::Scan(step) => w.line(format!("while (*at(p)) move({step});")),
+            Op::Output { offset } => w.line(format!("output({});", position(*offset))),
+            Op::Input { offset } => w.line(format!("input({});", position(*offset))),
+            Op::Loop(ops) => {
+                w.line("while (*at(p)) {");
+                w.indent();
+                body(w, ops);
+                w.dedent();
+                w.line("}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compare with a golden file, or rewrite it when `UPDATE_GOLDEN` is set
+    fn check_golden(name: &str, source: &str) {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let generated = generate(&program, &MachineConfig::default()).unwrap();
+        let path = format!("{}/tests/golden/{name}.c", env!("CARGO_MANIFEST_DIR"));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+        let golden = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(generated, golden, "{path} is out of date");
+    }
+
+    #[test]
+    fn test_golden_hello_world() {
+        check_golden("hello_world", include_str!("../../programs/hello_world.bf"));
+    }
+
+    #[test]
+    fn test_golden_rot13() {
+        check_golden("rot13", include_str!("../../programs/rot13.bf"));
+    }
+
+    #[test]
+    fn test_loop_indentation() {
+        // Nested loops are indented one level each
+        let program: BrainfuckProgram = ",[>,[.,]<-]".parse().unwrap();
+        let generated = generate(&program, &MachineConfig::default()).unwrap();
+        let main = &generated[generated.find("int main").unwrap()..];
+        assert_eq!(
+            main,
+            "int main(void) {
+    input(p);
+    while (*at(p)) {
+        input(p + 1);
+        move(1);
+        while (*at(p)) {
+            output(p);
+            input(p);
+        }
+        add(p - 1, -1);
+        move(-1);
+    }
+    return 0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_machine_config() {
+        // Cell type, tape and EOF policy show up in the prelude
+        let config = MachineConfig {
+            cell_width: CellWidth::I32,
+            overflow: Overflow::Saturate,
+            tape_length: TapeLength::Unbounded,
+            eof: EofBehavior::MinusOne,
+        };
+        let generated = generate(&BrainfuckProgram::default(), &config).unwrap();
+        assert!(generated.contains("typedef int32_t cell;"));
+        assert!(generated.contains("realloc"));
+        assert!(generated.contains("CELL_MAX ? CELL_MAX"));
+        assert!(generated.contains("(cell)-1"));
+        assert!(!generated.contains("TAPE_LEN"));
+    }
+}
@@ -0,0 +1,251 @@
+//! x86-64 Linux executable backend
+//!
+//! Writes a static ELF64 executable directly, without an assembler or
+//! linker. The machine code comes from the same [`Encoder`] as in the JIT,
+//! but talks to the kernel with `read`, `write` and `exit` syscalls instead
+//! of calling back into Rust.
+//!
+//! The file has two segments: the headers and code, and the tape, which like
+//! `.bss` takes up no space in the file.
+
+use super::{
+    x86::{patch, Encoder, Io},
+    CodegenError,
+};
+use crate::{ir, BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength};
+
+/// Address the file is loaded at
+const BASE: u64 = 0x400000;
+const PAGE_SIZE: u64 = 0x1000;
+const ELF_HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+/// Where the code starts, right after the ELF header and two program headers
+const CODE_OFFSET: u64 = ELF_HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+
+const OFF_TAPE: &[u8] = b"data pointer moved off the tape\n";
+const IO_ERROR: &[u8] = b"i/o error\n";
+
+/// Generate an executable behaving like `program` on the given machine
+pub fn generate(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+) -> Result<Vec<u8>, CodegenError> {
+    if config.cell_width != CellWidth::U8 {
+        return Err(CodegenError::Unsupported("cells wider than 8 bits"));
+    }
+    if config.overflow != Overflow::Wrap {
+        return Err(CodegenError::Unsupported(
+            "overflow policies other than wrapping",
+        ));
+    }
+    let TapeLength::Fixed(len) = config.tape_length else {
+        return Err(CodegenError::Unsupported("growing tapes"));
+    };
+    let len = i32::try_from(len)
+        .map_err(|_| CodegenError::Unsupported("tapes longer than 2^31 cells"))?;
+
+    let mut encoder = Encoder::default();
+    let tape = start(&mut encoder, len);
+    encoder
+        .body(&ir::lower(program), &Syscalls { eof: config.eof })
+        .map_err(|_| CodegenError::Unsupported("offsets beyond 32 bits"))?;
+    let mut code = finish(encoder);
+
+    let code_end = BASE + CODE_OFFSET + code.len() as u64;
+    let tape_address = code_end.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    patch(
+        &mut code,
+        tape,
+        (tape_address - BASE - CODE_OFFSET) as usize,
+    );
+
+    let mut out = Vec::with_capacity(CODE_OFFSET as usize + code.len());
+    elf_header(&mut out, BASE + CODE_OFFSET);
+    // Headers and code, readable and executable
+    program_header(&mut out, 0b101, 0, BASE, code_end - BASE, code_end - BASE);
+    // The tape, readable and writable and not in the file
+    program_header(&mut out, 0b110, 0, tape_address, 0, len as u64);
+    out.extend(code);
+    Ok(out)
+}
+
+fn elf_header(out: &mut Vec<u8>, entry: u64) {
+    // Magic, 64-bit, little endian, version 1, System V ABI
+    out.extend(b"\x7fELF\x02\x01\x01\x00");
+    out.extend([0; 8]);
+    out.extend(2u16.to_le_bytes()); // executable
+    out.extend(0x3eu16.to_le_bytes()); // x86-64
+    out.extend(1u32.to_le_bytes());
+    out.extend(entry.to_le_bytes());
+    out.extend(ELF_HEADER_SIZE.to_le_bytes()); // program headers
+    out.extend(0u64.to_le_bytes()); // no section headers
+    out.extend(0u32.to_le_bytes());
+    out.extend((ELF_HEADER_SIZE as u16).to_le_bytes());
+    out.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    out.extend(2u16.to_le_bytes());
+    out.extend(64u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+}
+
+/// A loadable segment
+fn program_header(
+    out: &mut Vec<u8>,
+    flags: u32,
+    offset: u64,
+    address: u64,
+    file_size: u64,
+    memory_size: u64,
+) {
+    out.extend(1u32.to_le_bytes()); // PT_LOAD
+    out.extend(flags.to_le_bytes());
+    out.extend(offset.to_le_bytes());
+    out.extend(address.to_le_bytes());
+    out.extend(address.to_le_bytes());
+    out.extend(file_size.to_le_bytes());
+    out.extend(memory_size.to_le_bytes());
+    out.extend(PAGE_SIZE.to_le_bytes());
+}
+
+/// Set up the registers, returning the displacement to patch with the
+/// address of the tape
+fn start(encoder: &mut Encoder, len: i32) -> usize {
+    let tape = encoder.jump(&[0x4c, 0x8d, 0x2d]); // lea r13, [rip + tape]
+    encoder.emit(&[0x4c, 0x89, 0xeb]); // mov rbx, r13
+    encoder.emit(&[0x4d, 0x8d, 0xb5]); // lea r14, [r13 + len]
+    encoder.emit_i32(len);
+    tape
+}
+
+/// Input and output with `read` and `write` syscalls on stdin and stdout
+struct Syscalls {
+    eof: EofBehavior,
+}
+
+impl Io for Syscalls {
+    fn output(&self, encoder: &mut Encoder, _offset: i32) {
+        encoder.emit(&[0x48, 0x89, 0xc6]); // mov rsi, rax
+        encoder.emit(&[0xb8, 1, 0, 0, 0]); // mov eax, 1 (write)
+        encoder.emit(&[0xbf, 1, 0, 0, 0]); // mov edi, 1 (stdout)
+        encoder.emit(&[0xba, 1, 0, 0, 0]); // mov edx, 1
+        syscall_checked(encoder);
+    }
+
+    fn input(&self, encoder: &mut Encoder, _offset: i32) {
+        // Read straight into the cell, which stays unchanged at the end of
+        // input
+        encoder.emit(&[0x48, 0x89, 0xc6]); // mov rsi, rax
+        encoder.emit(&[0x31, 0xc0]); // xor eax, eax (read)
+        encoder.emit(&[0x31, 0xff]); // xor edi, edi (stdin)
+        encoder.emit(&[0xba, 1, 0, 0, 0]); // mov edx, 1
+        syscall_checked(encoder);
+        let value = match self.eof {
+            EofBehavior::Unchanged => return,
+            EofBehavior::Zero => 0,
+            EofBehavior::MinusOne => 0xff,
+        };
+        let read = encoder.jump(&[0x0f, 0x85]); // jnz read
+        encoder.emit(&[0xc6, 0x06, value]); // mov byte [rsi], value
+        let end = encoder.here();
+        encoder.patch(read, end);
+    }
+}
+
+/// Make a syscall and bail out if it fails, leaving the flags set by
+/// `test rax, rax`
+fn syscall_checked(encoder: &mut Encoder) {
+    encoder.emit(&[0x0f, 0x05]); // syscall
+    encoder.emit(&[0x48, 0x85, 0xc0]); // test rax, rax
+    encoder.io_error_jump(&[0x0f, 0x88]); // js io_error
+}
+
+/// Emit the exit paths and messages and patch all jumps to them
+fn finish(mut encoder: Encoder) -> Vec<u8> {
+    encoder.emit(&[0x31, 0xff]); // xor edi, edi
+    let exit = encoder.jump(&[0xe9]); // jmp exit
+
+    let fault = encoder.here();
+    let off_tape = encoder.jump(&[0x48, 0x8d, 0x35]); // lea rsi, [rip + message]
+    encoder.emit(&[0xba]); // mov edx, length
+    encoder.emit_i32(OFF_TAPE.len() as i32);
+    let fault_fail = encoder.jump(&[0xe9]); // jmp fail
+
+    let io = encoder.here();
+    let io_error = encoder.jump(&[0x48, 0x8d, 0x35]); // lea rsi, [rip + message]
+    encoder.emit(&[0xba]); // mov edx, length
+    encoder.emit_i32(IO_ERROR.len() as i32);
+
+    // Print the message to stderr and exit with 1
+    let fail = encoder.here();
+    encoder.emit(&[0xb8, 1, 0, 0, 0]); // mov eax, 1 (write)
+    encoder.emit(&[0xbf, 2, 0, 0, 0]); // mov edi, 2 (stderr)
+    encoder.emit(&[0x0f, 0x05]); // syscall
+    encoder.emit(&[0xbf, 1, 0, 0, 0]); // mov edi, 1
+
+    let exit_code = encoder.here();
+    encoder.emit(&[0xb8, 60, 0, 0, 0]); // mov eax, 60 (exit)
+    encoder.emit(&[0x0f, 0x05]); // syscall
+
+    let off_tape_message = encoder.here();
+    encoder.emit(OFF_TAPE);
+    let io_error_message = encoder.here();
+    encoder.emit(IO_ERROR);
+
+    encoder.patch(exit, exit_code);
+    encoder.patch(fault_fail, fail);
+    encoder.patch(off_tape, off_tape_message);
+    encoder.patch(io_error, io_error_message);
+    encoder.patch_exits(fault, io);
+    encoder.code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_headers() {
+        // An x86-64 executable entering right after the headers
+        let program: BrainfuckProgram = "+[-]".parse().unwrap();
+        let elf = generate(&program, &MachineConfig::default()).unwrap();
+        assert_eq!(&elf[..4], b"\x7fELF");
+        assert_eq!(u16_at(&elf, 16), 2);
+        assert_eq!(u16_at(&elf, 18), 0x3e);
+        assert_eq!(u64_at(&elf, 24), BASE + CODE_OFFSET);
+        assert_eq!(u16_at(&elf, 56), 2);
+    }
+
+    #[test]
+    fn test_segments() {
+        // The code covers the whole file, the tape comes after it and is empty
+        let elf = generate(&BrainfuckProgram::default(), &MachineConfig::default()).unwrap();
+        let code = ELF_HEADER_SIZE as usize;
+        let tape = code + PROGRAM_HEADER_SIZE as usize;
+        assert_eq!(u64_at(&elf, code + 32), elf.len() as u64);
+        assert_eq!(u64_at(&elf, tape + 16) % PAGE_SIZE, 0);
+        assert!(u64_at(&elf, tape + 16) >= BASE + elf.len() as u64);
+        assert_eq!(u64_at(&elf, tape + 32), 0);
+        assert_eq!(u64_at(&elf, tape + 40), 30000);
+    }
+
+    #[test]
+    fn test_unsupported_config() {
+        // Only the machine model the JIT supports
+        let config = MachineConfig {
+            cell_width: CellWidth::U16,
+            ..Default::default()
+        };
+        assert!(matches!(
+            generate(&BrainfuckProgram::default(), &config),
+            Err(CodegenError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,285 @@
+//! LLVM IR backend
+//!
+//! Generates a textual LLVM IR module (`.ll`) with a `main` function using
+//! `getchar`/`putchar`, ready for `clang` or `llc`. The output uses opaque
+//! `ptr` types, so it needs LLVM 15 or later, or `-opaque-pointers` on
+//! LLVM 14.
+//!
+//! The data pointer lives in an `alloca` that LLVM promotes to a register.
+//! Every cell access goes through a small bounds checked `@at` function that
+//! gets inlined when optimising.
+
+use super::{CodegenError, Writer};
+use crate::{
+    ir::{self, Op},
+    BrainfuckProgram, EofBehavior, MachineConfig, Overflow, TapeLength,
+};
+
+/// Message printed when the data pointer leaves the tape
+const OFF_TAPE: &str = "data pointer moved off the tape to cell %ld\n";
+
+/// Generate an LLVM IR module behaving like `program` on the given machine
+pub fn generate(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+) -> Result<String, CodegenError> {
+    if config.overflow != Overflow::Wrap {
+        return Err(CodegenError::Unsupported(
+            "overflow policies other than wrapping",
+        ));
+    }
+    let TapeLength::Fixed(len) = config.tape_length else {
+        return Err(CodegenError::Unsupported("growing tapes"));
+    };
+    let cell = format!("i{}", config.cell_width.bits());
+    let tape = format!("[{len} x {cell}]");
+
+    let mut w = Writer::new("  ");
+    w.line("; Generated by brainfuck-parser");
+    w.line(format!("@tape = internal global {tape} zeroinitializer"));
+    w.line(format!(
+        "@off_tape = private constant [{} x i8] c\"{}\\00\"",
+        OFF_TAPE.len() + 1,
+        OFF_TAPE.replace('\n', "\\0A")
+    ));
+    w.line("");
+    w.lines(
+        "declare i32 @getchar()
+declare i32 @putchar(i32)
+declare i32 @dprintf(i32, ptr, ...)
+declare void @exit(i32) noreturn",
+    );
+    w.line("");
+    w.line("; The cell at position %i, stopping if it is off the tape");
+    w.lines(&format!(
+        "define internal ptr @at(i64 %i) {{
+entry:
+  %on_tape = icmp ult i64 %i, {len}
+  br i1 %on_tape, label %found, label %off_tape
+found:
+  %cell = getelementptr inbounds {tape}, ptr @tape, i64 0, i64 %i
+  ret ptr %cell
+off_tape:
+  call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @off_tape, i64 %i)
+  call void @exit(i32 1)
+  unreachable
+}}"
+    ));
+    w.line("");
+    w.line("define i32 @main() {");
+    w.line("entry:");
+    w.indent();
+    w.line("%p = alloca i64");
+    w.line("store i64 0, ptr %p");
+    let mut function = Function {
+        w: &mut w,
+        config,
+        cell,
+        temps: 0,
+        labels: 0,
+    };
+    function.ops(&ir::lower(program));
+    w.line("ret i32 0");
+    w.dedent();
+    w.line("}");
+    Ok(w.finish())
+}
+
+/// Writes the body of `main`, numbering temporaries and labels
+struct Function<'a> {
+    w: &'a mut Writer,
+    config: &'a MachineConfig,
+    /// LLVM type of a cell
+    cell: String,
+    temps: usize,
+    labels: usize,
+}
+
+impl Function<'_> {
+    fn ops(&mut self, ops: &[Op]) {
+        for op in ops {
+            self.op(op);
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        let cell = self.cell.clone();
+        match op {
+            Op::Add { offset, delta } => {
+                let at = self.at(*offset);
+                let value = self.temp(format!("load {cell}, ptr {at}"));
+                let delta = self.constant(i64::from(*delta));
+                let sum = self.temp(format!("add {cell} {value}, {delta}"));
+                self.w.line(format!("store {cell} {sum}, ptr {at}"));
+            }
+            Op::Move(distance) => self.move_by(*distance),
+            Op::SetZero { offset } => {
+                let at = self.at(*offset);
+                self.w.line(format!("store {cell} 0, ptr {at}"));
+            }
+            Op::AddMul { offset, factor } => {
+                let current = self.load(0);
+                let factor = self.constant(i64::from(*factor));
+                let product = self.temp(format!("mul {cell} {current}, {factor}"));
+                let at = self.at(*offset);
+                let value = self.temp(format!("load {cell}, ptr {at}"));
+                let sum = self.temp(format!("add {cell} {value}, {product}"));
+                self.w.line(format!("store {cell} {sum}, ptr {at}"));
+            }
+            Op::Scan(step) => {
+                let step = *step;
+                self.while_nonzero("scan", |f| f.move_by(step));
+            }
+            Op::Output { offset } => {
+                let value = self.load(*offset);
+                let byte = self.resize(&value, &cell, "i32", "zext");
+                self.w.line(format!("call i32 @putchar(i32 {byte})"));
+            }
+            Op::Input { offset } => {
+                let read = self.temp("call i32 @getchar()");
+                let at = self.at(*offset);
+                let byte = self.resize(&read, "i32", &cell, "trunc");
+                // getchar returns -1 at the end of input, which already has
+                // all bits set for EofBehavior::MinusOne
+                let value = match self.config.eof {
+                    EofBehavior::MinusOne => byte,
+                    eof => {
+                        let at_eof = self.temp(format!("icmp slt i32 {read}, 0"));
+                        let fallback = match eof {
+                            EofBehavior::Zero => "0".to_string(),
+                            _ => self.temp(format!("load {cell}, ptr {at}")),
+                        };
+                        self.temp(format!(
+                            "select i1 {at_eof}, {cell} {fallback}, {cell} {byte}"
+                        ))
+                    }
+                };
+                self.w.line(format!("store {cell} {value}, ptr {at}"));
+            }
+            Op::Loop(body) => self.while_nonzero("loop", |f| f.ops(body)),
+        }
+    }
+
+    /// Emit `body` as a loop running while the current cell is not zero
+    fn while_nonzero(&mut self, kind: &str, body: impl FnOnce(&mut Self)) {
+        let n = self.labels;
+        self.labels += 1;
+        let (cond, start, end) = (
+            format!("{kind}{n}.cond"),
+            format!("{kind}{n}.body"),
+            format!("{kind}{n}.end"),
+        );
+        self.w.line(format!("br label %{cond}"));
+        self.label(&cond);
+        let value = self.load(0);
+        let nonzero = self.temp(format!("icmp ne {} {value}, 0", self.cell));
+        self.w
+            .line(format!("br i1 {nonzero}, label %{start}, label %{end}"));
+        self.label(&start);
+        body(self);
+        self.w.line(format!("br label %{cond}"));
+        self.label(&end);
+    }
+
+    /// Start a new basic block
+    fn label(&mut self, name: &str) {
+        self.w.dedent();
+        self.w.line(format!("{name}:"));
+        self.w.indent();
+    }
+
+    /// Emit an instruction producing a value and return its name
+    fn temp(&mut self, instruction: impl AsRef<str>) -> String {
+        let name = format!("%t{}", self.temps);
+        self.temps += 1;
+        self.w.line(format!("{name} = {}", instruction.as_ref()));
+        name
+    }
+
+    /// Pointer to the cell at `offset`
+    fn at(&mut self, offset: isize) -> String {
+        let mut position = self.temp("load i64, ptr %p");
+        if offset != 0 {
+            position = self.temp(format!("add i64 {position}, {offset}"));
+        }
+        self.temp(format!("call ptr @at(i64 {position})"))
+    }
+
+    /// Value of the cell at `offset`
+    fn load(&mut self, offset: isize) -> String {
+        let at = self.at(offset);
+        self.temp(format!("load {}, ptr {at}", self.cell))
+    }
+
+    /// Move the pointer, stopping if it leaves the tape
+    fn move_by(&mut self, distance: isize) {
+        let position = self.temp("load i64, ptr %p");
+        let moved = self.temp(format!("add i64 {position}, {distance}"));
+        self.w.line(format!("store i64 {moved}, ptr %p"));
+        self.temp(format!("call ptr @at(i64 {moved})"));
+    }
+
+    /// Convert between integer types, a no-op when they are the same
+    fn resize(&mut self, value: &str, from: &str, to: &str, op: &str) -> String {
+        if from == to {
+            value.to_string()
+        } else {
+            self.temp(format!("{op} {from} {value} to {to}"))
+        }
+    }
+
+    /// A constant of the cell type, wrapped to the signed range LLVM expects
+    fn constant(&self, value: i64) -> i64 {
+        let unused = 64 - self.config.cell_width.bits();
+        (value << unused) >> unused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CellWidth;
+
+    #[test]
+    fn test_loop_labels() {
+        // Loops become conditional branches around a body block
+        let program: BrainfuckProgram = ",[.,]".parse().unwrap();
+        let generated = generate(&program, &MachineConfig::default()).unwrap();
+        let main = &generated[generated.find("define i32 @main").unwrap()..];
+        assert!(main.contains("  br label %loop0.cond\nloop0.cond:\n"));
+        assert!(main.contains("br i1 %t10, label %loop0.body, label %loop0.end\nloop0.body:\n"));
+        assert!(main.ends_with("  br label %loop0.cond\nloop0.end:\n  ret i32 0\n}\n"));
+    }
+
+    #[test]
+    fn test_cell_width() {
+        // Cells use the configured integer type and constants fit into it
+        let program: BrainfuckProgram = "-.".parse().unwrap();
+        for (cell_width, add, output) in [
+            (CellWidth::U8, "add i8 %t2, -1", "zext i8"),
+            (CellWidth::U16, "add i16 %t2, -1", "zext i16"),
+            (CellWidth::I32, "add i32 %t2, -1", "@putchar(i32 %t"),
+        ] {
+            let config = MachineConfig {
+                cell_width,
+                ..Default::default()
+            };
+            let generated = generate(&program, &config).unwrap();
+            assert!(generated.contains(add), "{generated}");
+            assert!(generated.contains(output), "{generated}");
+        }
+    }
+
+    #[test]
+    fn test_unsupported_config() {
+        // The tape is a fixed global array and cells always wrap
+        let config = MachineConfig {
+            tape_length: TapeLength::Unbounded,
+            ..Default::default()
+        };
+        assert!(matches!(
+            generate(&BrainfuckProgram::default(), &config),
+            Err(CodegenError::Unsupported(_))
+        ));
+    }
+}
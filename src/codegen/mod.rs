@@ -0,0 +1,79 @@
+//! Backends turning programs into code for other languages and platforms
+//!
+//! Every backend lowers the program with [`ir::lower`](crate::ir::lower)
+//! first and follows the [`MachineConfig`](crate::MachineConfig) it is given.
+
+use std::fmt;
+
+pub mod asm;
+pub mod c;
+pub mod elf;
+pub mod llvm;
+pub mod rust;
+pub mod wasm;
+pub(crate) mod x86;
+
+/// Why a program could not be turned into code
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CodegenError {
+    /// The backend cannot express this part of the machine model
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenError::Unsupported(what) => write!(f, "the backend does not support {what}"),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// Collects generated source line by line, keeping track of indentation
+struct Writer {
+    out: String,
+    depth: usize,
+    indent: &'static str,
+}
+
+impl Writer {
+    fn new(indent: &'static str) -> Self {
+        Writer {
+            out: String::new(),
+            depth: 0,
+            indent,
+        }
+    }
+
+    /// Write a line at the current indentation, empty lines stay empty
+    fn line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+        if !line.is_empty() {
+            for _ in 0..self.depth {
+                self.out.push_str(self.indent);
+            }
+            self.out.push_str(line);
+        }
+        self.out.push('\n');
+    }
+
+    /// Write several lines, e.g. a fixed block of code
+    fn lines(&mut self, lines: &str) {
+        for line in lines.lines() {
+            self.line(line);
+        }
+    }
+
+    fn indent(&mut self) {
+        self.depth += 1;
+    }
+
+    fn dedent(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
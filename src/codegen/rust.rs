@@ -0,0 +1,325 @@
+//! Rust backend
+//!
+//! Generates Rust source with a `run` function reading from any [`Read`] and
+//! writing to any [`Write`](std::io::Write), optionally wrapped in a `main`
+//! using stdin and stdout. Errors such as leaving the tape are returned as
+//! [`io::Error`](std::io::Error)s.
+//!
+//! [`Read`]: std::io::Read
+
+use super::{CodegenError, Writer};
+use crate::{
+    ir::{self, Op},
+    BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength,
+};
+
+/// What the generated source provides
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Entry {
+    /// A standalone program with a `fn main()` using stdin and stdout
+    #[default]
+    Main,
+    /// Only `fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()>`
+    Function,
+}
+
+/// Generate Rust source behaving like `program` on the given machine
+pub fn generate(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+    entry: Entry,
+) -> Result<String, CodegenError> {
+    let ops = ir::lower_for(program, config);
+    let mut w = Writer::new("    ");
+    w.line("// Generated by brainfuck-parser");
+    w.line("use std::io::{self, Read, Write};");
+    w.line("");
+    tape(&mut w, config);
+
+    w.line("/// Run the brainfuck program");
+    w.line("pub fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {");
+    w.indent();
+    if !uses(&ops, &|op| matches!(op, Op::Input { .. })) {
+        w.line("let _ = input;");
+    }
+    if !ops.is_empty() {
+        w.line("let mut tape = Tape::new();");
+        let moves = uses(&ops, &|op| matches!(op, Op::Move(_) | Op::Scan(_)));
+        w.line(format!(
+            "let {}p: isize = 0;",
+            if moves { "mut " } else { "" }
+        ));
+        body(&mut w, &ops, config);
+    }
+    w.line("output.flush()");
+    w.dedent();
+    w.line("}");
+
+    if entry == Entry::Main {
+        w.lines(
+            "
+fn main() {
+    if let Err(e) = run(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+        eprintln!(\"{e}\");
+        std::process::exit(1);
+    }
+}",
+        );
+    }
+    Ok(w.finish())
+}
+
+/// Rust type of a cell
+fn cell_type(cell_width: CellWidth) -> &'static str {
+    match cell_width {
+        CellWidth::U8 => "u8",
+        CellWidth::U16 => "u16",
+        CellWidth::U32 => "u32",
+        CellWidth::I32 => "i32",
+    }
+}
+
+/// The `Tape` type and its helper methods used by the body
+fn tape(w: &mut Writer, config: &MachineConfig) {
+    w.line(format!("type Cell = {};", cell_type(config.cell_width)));
+    w.line("");
+    w.line("struct Tape {");
+    w.indent();
+    w.line("cells: Vec<Cell>,");
+    if config.tape_length == TapeLength::Unbounded {
+        w.line("origin: isize,");
+    }
+    w.dedent();
+    w.line("}");
+    w.line("");
+    w.line("#[allow(dead_code)]");
+    w.line("impl Tape {");
+    w.indent();
+
+    match config.tape_length {
+        TapeLength::Fixed(len) => w.lines(&format!(
+            "fn new() -> Self {{
+    Tape {{
+        cells: vec![0; {len}],
+    }}
+}}
+
+/// The cell at position `i`, failing if it is off the tape
+fn at(&mut self, i: isize) -> io::Result<&mut Cell> {{
+    usize::try_from(i)
+        .ok()
+        .and_then(|i| self.cells.get_mut(i))
+        .ok_or_else(|| Self::off_tape(i))
+}}"
+        )),
+        TapeLength::Growable => w.lines(
+            "fn new() -> Self {
+    Tape { cells: Vec::new() }
+}
+
+/// The cell at position `i`, growing the tape to the right as needed
+fn at(&mut self, i: isize) -> io::Result<&mut Cell> {
+    let i = usize::try_from(i).map_err(|_| Self::off_tape(i))?;
+    if i >= self.cells.len() {
+        self.cells.resize((i + 1).max(2 * self.cells.len()), 0);
+    }
+    Ok(&mut self.cells[i])
+}",
+        ),
+        TapeLength::Unbounded => w.lines(
+            "fn new() -> Self {
+    Tape {
+        cells: Vec::new(),
+        origin: 0,
+    }
+}
+
+/// The cell at position `i`, growing the tape in either direction as needed
+fn at(&mut self, i: isize) -> io::Result<&mut Cell> {
+    if i + self.origin < 0 {
+        let grow = (-(i + self.origin)).max(self.cells.len() as isize);
+        self.cells.splice(0..0, std::iter::repeat(0).take(grow as usize));
+        self.origin += grow;
+    }
+    let i = (i + self.origin) as usize;
+    if i >= self.cells.len() {
+        self.cells.resize((i + 1).max(2 * self.cells.len()), 0);
+    }
+    Ok(&mut self.cells[i])
+}",
+        ),
+    }
+    w.line("");
+
+    w.line("fn add(&mut self, i: isize, delta: i64) -> io::Result<()> {");
+    w.indent();
+    w.line("let cell = self.at(i)?;");
+    match config.overflow {
+        // Truncating the delta keeps exactly the low bits
+        Overflow::Wrap => w.line("*cell = cell.wrapping_add(delta as Cell);"),
+        Overflow::Saturate => w.lines(
+            "let value = i64::from(*cell).saturating_add(delta);
+*cell = value.clamp(Cell::MIN.into(), Cell::MAX.into()) as Cell;",
+        ),
+        Overflow::Error => w.lines(
+            "*cell = i64::from(*cell)
+    .checked_add(delta)
+    .and_then(|value| Cell::try_from(value).ok())
+    .ok_or_else(|| io::Error::other(format!(\"cell {i} overflowed\")))?;",
+        ),
+    }
+    w.line("Ok(())");
+    w.dedent();
+    w.line("}");
+    w.line("");
+
+    w.lines(
+        "fn output(&mut self, i: isize, output: &mut impl Write) -> io::Result<()> {
+    output.write_all(&[*self.at(i)? as u8])
+}
+
+fn input(&mut self, i: isize, input: &mut impl Read) -> io::Result<()> {
+    match input.bytes().next().transpose()? {
+        Some(byte) => *self.at(i)? = byte.into(),",
+    );
+    w.indent();
+    w.indent();
+    match config.eof {
+        EofBehavior::Unchanged => w.line("None => {}"),
+        EofBehavior::Zero => w.line("None => *self.at(i)? = 0,"),
+        EofBehavior::MinusOne => w.line("None => *self.at(i)? = -1i64 as Cell,"),
+    }
+    w.dedent();
+    w.line("}");
+    w.line("Ok(())");
+    w.dedent();
+    w.line("}");
+    w.line("");
+
+    w.lines(
+        "fn off_tape(i: isize) -> io::Error {
+    io::Error::other(format!(\"data pointer moved off the tape to cell {i}\"))
+}",
+    );
+    w.dedent();
+    w.line("}");
+    w.line("");
+}
+
+/// Whether any op, including those in loops, matches `predicate`
+fn uses(ops: &[Op], predicate: &dyn Fn(&Op) -> bool) -> bool {
+    ops.iter().any(|op| match op {
+        Op::Loop(body) => predicate(op) || uses(body, predicate),
+        op => predicate(op),
+    })
+}
+
+/// `p` plus an offset, as a Rust expression
+fn position(offset: isize) -> String {
+    match offset {
+        0 => "p".to_string(),
+        offset if offset < 0 => format!("p - {}", -offset),
+        offset => format!("p + {offset}"),
+    }
+}
+
+fn body(w: &mut Writer, ops: &[Op], config: &MachineConfig) {
+    for op in ops {
+        match op {
+            Op::Add { offset, delta } => {
+                w.line(format!("tape.add({}, {delta})?;", position(*offset)))
+            }
+            Op::Move(distance) => {
+                w.line(format!("p += {distance};"));
+                w.line("tape.at(p)?;");
+            }
+            Op::SetZero { offset } => w.line(format!("*tape.at({})? = 0;", position(*offset))),
+            Op::AddMul { offset, factor } => {
+                // Same as the interpreter: only wrapping may drop high bits
+                let mul = match config.overflow {
+                    Overflow::Wrap => "wrapping_mul",
+                    _ => "saturating_mul",
+                };
+                w.line(format!(
+                    "let value = i64::from(*tape.at(p)?).{mul}({factor});"
+                ));
+                w.line(format!("tape.add({}, value)?;", position(*offset)));
+            }
+            Op::Scan(step) => w.line(format!("while *tape.at(p)? != 0 {{ p += {step}; }}")),
+            Op::Output { offset } => {
+                w.line(format!("tape.output({}, output)?;", position(*offset)))
+            }
+            Op::Input { offset } => w.line(format!("tape.input({}, input)?;", position(*offset))),
+            Op::Loop(ops) => {
+                w.line("while *tape.at(p)? != 0 {");
+                w.indent();
+                body(w, ops, config);
+                w.dedent();
+                w.line("}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        // Only the standalone program gets a `main`
+        let program: BrainfuckProgram = "+.".parse().unwrap();
+        let config = MachineConfig::default();
+        let main = generate(&program, &config, Entry::Main).unwrap();
+        let function = generate(&program, &config, Entry::Function).unwrap();
+        assert!(main.contains("fn main()"));
+        assert!(!function.contains("fn main()"));
+        assert!(function.contains("pub fn run(input: &mut impl Read, output: &mut impl Write)"));
+    }
+
+    #[test]
+    fn test_loop_indentation() {
+        // Nested loops are indented one level each
+        let program: BrainfuckProgram = ",[>,[.,]<-]".parse().unwrap();
+        let generated = generate(&program, &MachineConfig::default(), Entry::Function).unwrap();
+        let run = &generated[generated.find("pub fn run").unwrap()..];
+        assert_eq!(
+            run,
+            "pub fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {
+    let mut tape = Tape::new();
+    let mut p: isize = 0;
+    tape.input(p, input)?;
+    while *tape.at(p)? != 0 {
+        tape.input(p + 1, input)?;
+        p += 1;
+        tape.at(p)?;
+        while *tape.at(p)? != 0 {
+            tape.output(p, output)?;
+            tape.input(p, input)?;
+        }
+        tape.add(p - 1, -1)?;
+        p += -1;
+        tape.at(p)?;
+    }
+    output.flush()
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_machine_config() {
+        // Cell type, tape and EOF policy show up in the generated code
+        let config = MachineConfig {
+            cell_width: CellWidth::I32,
+            overflow: Overflow::Saturate,
+            tape_length: TapeLength::Unbounded,
+            eof: EofBehavior::MinusOne,
+        };
+        let generated = generate(&BrainfuckProgram::default(), &config, Entry::Main).unwrap();
+        assert!(generated.contains("type Cell = i32;"));
+        assert!(generated.contains("origin: isize"));
+        assert!(generated.contains("clamp"));
+        assert!(generated.contains("-1i64 as Cell"));
+    }
+}
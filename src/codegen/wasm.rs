@@ -0,0 +1,585 @@
+//! WebAssembly backend
+//!
+//! Generates a module importing `env.read_byte: () -> i32` (returning `-1` at
+//! the end of input) and `env.write_byte: (i32) -> ()`, and exporting its
+//! `memory` and a `run` function. The tape lives at the start of linear
+//! memory. Every access is bounds checked and traps when the pointer is off
+//! the tape.
+//!
+//! Both the text format and the binary format are produced from the same
+//! instruction list, so the two always describe the same module.
+
+use super::{CodegenError, Writer};
+use crate::{
+    ir::{self, Op},
+    BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength,
+};
+
+/// Size of a WebAssembly memory page
+const PAGE_SIZE: u64 = 65536;
+
+// Function indices, the imports come first
+const READ_BYTE: u32 = 0;
+const WRITE_BYTE: u32 = 1;
+const AT: u32 = 2;
+
+// Locals of `run`
+const P: u32 = 0;
+const ADDRESS: u32 = 1;
+const VALUE: u32 = 2;
+
+/// Generate a module in the WebAssembly text format
+pub fn generate_text(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+) -> Result<String, CodegenError> {
+    let module = Module::new(program, config)?;
+    let mut w = Writer::new("  ");
+    w.line(";; Generated by brainfuck-parser");
+    w.line("(module");
+    w.indent();
+    w.line("(import \"env\" \"read_byte\" (func $read_byte (result i32)))");
+    w.line("(import \"env\" \"write_byte\" (func $write_byte (param i32)))");
+    w.line(format!("(memory (export \"memory\") {})", module.pages));
+    w.line("");
+    w.line(";; Byte address of cell $i, trapping if it is off the tape");
+    w.line("(func $at (param $i i32) (result i32)");
+    w.indent();
+    print(&mut w, &module.at, &["$i"]);
+    w.dedent();
+    w.line(")");
+    w.line("");
+    w.line("(func (export \"run\") (local $p i32) (local $a i32) (local $v i32)");
+    w.indent();
+    print(&mut w, &module.run, &["$p", "$a", "$v"]);
+    w.dedent();
+    w.line(")");
+    w.dedent();
+    w.line(")");
+    Ok(w.finish())
+}
+
+/// Generate a module in the WebAssembly binary format
+pub fn generate_binary(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+) -> Result<Vec<u8>, CodegenError> {
+    let module = Module::new(program, config)?;
+    let mut out = b"\0asm\x01\0\0\0".to_vec();
+
+    // Types: () -> i32, (i32) -> (), (i32) -> i32, () -> ()
+    section(&mut out, 1, |s| {
+        s.push(4);
+        s.extend([0x60, 0, 1, I32]);
+        s.extend([0x60, 1, I32, 0]);
+        s.extend([0x60, 1, I32, 1, I32]);
+        s.extend([0x60, 0, 0]);
+    });
+    section(&mut out, 2, |s| {
+        s.push(2);
+        for (name, ty) in [("read_byte", 0), ("write_byte", 1)] {
+            name_bytes(s, "env");
+            name_bytes(s, name);
+            s.extend([0x00, ty]);
+        }
+    });
+    // `at` and `run`
+    section(&mut out, 3, |s| s.extend([2, 2, 3]));
+    section(&mut out, 5, |s| {
+        s.extend([1, 0x00]);
+        unsigned(s, module.pages);
+    });
+    section(&mut out, 7, |s| {
+        s.push(2);
+        name_bytes(s, "memory");
+        s.extend([0x02, 0]);
+        name_bytes(s, "run");
+        s.push(0x00);
+        unsigned(s, AT + 1);
+    });
+    section(&mut out, 10, |s| {
+        s.push(2);
+        function_body(s, &[], &module.at);
+        function_body(s, &[(3, I32)], &module.run);
+    });
+    Ok(out)
+}
+
+/// The `i32` value type
+const I32: u8 = 0x7f;
+
+/// The instructions used by the generated code
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Ins {
+    Unreachable,
+    Block,
+    Loop,
+    If,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Call(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    Const(i32),
+    Eqz,
+    GeS,
+    GeU,
+    Add,
+    Mul,
+    Load(CellWidth),
+    Store(CellWidth),
+}
+
+/// Everything that differs between programs
+struct Module {
+    pages: u32,
+    at: Vec<Ins>,
+    run: Vec<Ins>,
+}
+
+impl Module {
+    fn new(program: &BrainfuckProgram, config: &MachineConfig) -> Result<Self, CodegenError> {
+        if config.overflow != Overflow::Wrap {
+            return Err(CodegenError::Unsupported(
+                "overflow policies other than wrapping",
+            ));
+        }
+        let TapeLength::Fixed(len) = config.tape_length else {
+            return Err(CodegenError::Unsupported("growing tapes"));
+        };
+        let cell_size = u64::from(config.cell_width.bits() / 8);
+        let bytes = len as u64 * cell_size;
+        if bytes > u64::from(u32::MAX) {
+            return Err(CodegenError::Unsupported("tapes larger than 4 GiB"));
+        }
+
+        // Unsigned comparison also catches negative positions
+        let at = vec![
+            Ins::LocalGet(0),
+            Ins::Const(len as i32),
+            Ins::GeU,
+            Ins::If,
+            Ins::Unreachable,
+            Ins::End,
+            Ins::LocalGet(0),
+            Ins::Const(cell_size as i32),
+            Ins::Mul,
+        ];
+        let mut run = Vec::new();
+        Lowering {
+            config,
+            out: &mut run,
+        }
+        .ops(&ir::lower(program))?;
+
+        Ok(Module {
+            pages: bytes.div_ceil(PAGE_SIZE).max(1) as u32,
+            at,
+            run,
+        })
+    }
+}
+
+/// Turns IR into instructions for the body of `run`
+struct Lowering<'a> {
+    config: &'a MachineConfig,
+    out: &'a mut Vec<Ins>,
+}
+
+impl Lowering<'_> {
+    fn ops(&mut self, ops: &[Op]) -> Result<(), CodegenError> {
+        for op in ops {
+            self.op(op)?;
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, op: &Op) -> Result<(), CodegenError> {
+        let width = self.config.cell_width;
+        match op {
+            Op::Add { offset, delta } => {
+                self.modify(*offset)?;
+                self.out
+                    .extend([Ins::Const(*delta), Ins::Add, Ins::Store(width)]);
+            }
+            Op::Move(distance) => self.move_by(*distance)?,
+            Op::SetZero { offset } => {
+                self.address(*offset)?;
+                self.out.extend([Ins::Const(0), Ins::Store(width)]);
+            }
+            Op::AddMul { offset, factor } => {
+                self.modify(*offset)?;
+                self.load(0)?;
+                self.out
+                    .extend([Ins::Const(*factor), Ins::Mul, Ins::Add, Ins::Store(width)]);
+            }
+            Op::Scan(step) => {
+                self.out.extend([Ins::Block, Ins::Loop]);
+                self.load(0)?;
+                self.out.extend([Ins::Eqz, Ins::BrIf(1)]);
+                self.move_by(*step)?;
+                self.out.extend([Ins::Br(0), Ins::End, Ins::End]);
+            }
+            Op::Output { offset } => {
+                self.load(*offset)?;
+                self.out.push(Ins::Call(WRITE_BYTE));
+            }
+            Op::Input { offset } => {
+                self.out
+                    .extend([Ins::Call(READ_BYTE), Ins::LocalSet(VALUE)]);
+                match self.config.eof {
+                    EofBehavior::Unchanged => {
+                        self.out
+                            .extend([Ins::LocalGet(VALUE), Ins::Const(0), Ins::GeS, Ins::If]);
+                        self.address(*offset)?;
+                        self.out
+                            .extend([Ins::LocalGet(VALUE), Ins::Store(width), Ins::End]);
+                    }
+                    EofBehavior::Zero => {
+                        self.address(*offset)?;
+                        self.out.extend([
+                            Ins::LocalGet(VALUE),
+                            Ins::Const(0),
+                            Ins::LocalGet(VALUE),
+                            Ins::Const(0),
+                            Ins::GeS,
+                            Ins::Select,
+                            Ins::Store(width),
+                        ]);
+                    }
+                    // Storing -1 leaves all bits of the cell set
+                    EofBehavior::MinusOne => {
+                        self.address(*offset)?;
+                        self.out.extend([Ins::LocalGet(VALUE), Ins::Store(width)]);
+                    }
+                }
+            }
+            Op::Loop(body) => {
+                self.out.push(Ins::Block);
+                self.load(0)?;
+                self.out.extend([Ins::Eqz, Ins::BrIf(0), Ins::Loop]);
+                self.ops(body)?;
+                self.load(0)?;
+                self.out.extend([Ins::BrIf(0), Ins::End, Ins::End]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Push the address of the cell at `offset`
+    fn address(&mut self, offset: isize) -> Result<(), CodegenError> {
+        self.out.push(Ins::LocalGet(P));
+        if offset != 0 {
+            self.out.extend([Ins::Const(constant(offset)?), Ins::Add]);
+        }
+        self.out.push(Ins::Call(AT));
+        Ok(())
+    }
+
+    /// Push the value of the cell at `offset`
+    fn load(&mut self, offset: isize) -> Result<(), CodegenError> {
+        self.address(offset)?;
+        self.out.push(Ins::Load(self.config.cell_width));
+        Ok(())
+    }
+
+    /// Push the address and then the value of the cell at `offset`, ready
+    /// for computing and storing a new value
+    fn modify(&mut self, offset: isize) -> Result<(), CodegenError> {
+        self.address(offset)?;
+        self.out.extend([
+            Ins::LocalTee(ADDRESS),
+            Ins::LocalGet(ADDRESS),
+            Ins::Load(self.config.cell_width),
+        ]);
+        Ok(())
+    }
+
+    /// Move the pointer, trapping if it leaves the tape
+    fn move_by(&mut self, distance: isize) -> Result<(), CodegenError> {
+        self.out.extend([
+            Ins::LocalGet(P),
+            Ins::Const(constant(distance)?),
+            Ins::Add,
+            Ins::LocalTee(P),
+            Ins::Call(AT),
+            Ins::Drop,
+        ]);
+        Ok(())
+    }
+}
+
+fn constant(value: isize) -> Result<i32, CodegenError> {
+    i32::try_from(value).map_err(|_| CodegenError::Unsupported("offsets beyond 32 bits"))
+}
+
+/// Load and store opcodes and the log2 of their alignment
+fn memory_op(width: CellWidth) -> (&'static str, u8, &'static str, u8, u8) {
+    match width {
+        CellWidth::U8 => ("i32.load8_u", 0x2d, "i32.store8", 0x3a, 0),
+        CellWidth::U16 => ("i32.load16_u", 0x2f, "i32.store16", 0x3b, 1),
+        CellWidth::U32 | CellWidth::I32 => ("i32.load", 0x28, "i32.store", 0x36, 2),
+    }
+}
+
+/// Write instructions in the text format, one per line
+fn print(w: &mut Writer, code: &[Ins], locals: &[&str]) {
+    for ins in code {
+        let text = match *ins {
+            Ins::Unreachable => "unreachable".to_string(),
+            Ins::Block => "block".to_string(),
+            Ins::Loop => "loop".to_string(),
+            Ins::If => "if".to_string(),
+            Ins::End => {
+                w.dedent();
+                "end".to_string()
+            }
+            Ins::Br(depth) => format!("br {depth}"),
+            Ins::BrIf(depth) => format!("br_if {depth}"),
+            Ins::Call(function) => {
+                let name = ["$read_byte", "$write_byte", "$at"][function as usize];
+                format!("call {name}")
+            }
+            Ins::Drop => "drop".to_string(),
+            Ins::Select => "select".to_string(),
+            Ins::LocalGet(local) => format!("local.get {}", locals[local as usize]),
+            Ins::LocalSet(local) => format!("local.set {}", locals[local as usize]),
+            Ins::LocalTee(local) => format!("local.tee {}", locals[local as usize]),
+            Ins::Const(value) => format!("i32.const {value}"),
+            Ins::Eqz => "i32.eqz".to_string(),
+            Ins::GeS => "i32.ge_s".to_string(),
+            Ins::GeU => "i32.ge_u".to_string(),
+            Ins::Add => "i32.add".to_string(),
+            Ins::Mul => "i32.mul".to_string(),
+            Ins::Load(width) => memory_op(width).0.to_string(),
+            Ins::Store(width) => memory_op(width).2.to_string(),
+        };
+        w.line(text);
+        if matches!(ins, Ins::Block | Ins::Loop | Ins::If) {
+            w.indent();
+        }
+    }
+}
+
+/// Write a function body in the binary format
+fn function_body(out: &mut Vec<u8>, locals: &[(u32, u8)], code: &[Ins]) {
+    let mut body = Vec::new();
+    unsigned(&mut body, locals.len() as u32);
+    for &(count, ty) in locals {
+        unsigned(&mut body, count);
+        body.push(ty);
+    }
+    for ins in code {
+        match *ins {
+            Ins::Unreachable => body.push(0x00),
+            Ins::Block => body.extend([0x02, 0x40]),
+            Ins::Loop => body.extend([0x03, 0x40]),
+            Ins::If => body.extend([0x04, 0x40]),
+            Ins::End => body.push(0x0b),
+            Ins::Br(depth) => {
+                body.push(0x0c);
+                unsigned(&mut body, depth);
+            }
+            Ins::BrIf(depth) => {
+                body.push(0x0d);
+                unsigned(&mut body, depth);
+            }
+            Ins::Call(function) => {
+                body.push(0x10);
+                unsigned(&mut body, function);
+            }
+            Ins::Drop => body.push(0x1a),
+            Ins::Select => body.push(0x1b),
+            Ins::LocalGet(local) => {
+                body.push(0x20);
+                unsigned(&mut body, local);
+            }
+            Ins::LocalSet(local) => {
+                body.push(0x21);
+                unsigned(&mut body, local);
+            }
+            Ins::LocalTee(local) => {
+                body.push(0x22);
+                unsigned(&mut body, local);
+            }
+            Ins::Const(value) => {
+                body.push(0x41);
+                signed(&mut body, value);
+            }
+            Ins::Eqz => body.push(0x45),
+            Ins::GeS => body.push(0x4e),
+            Ins::GeU => body.push(0x4f),
+            Ins::Add => body.push(0x6a),
+            Ins::Mul => body.push(0x6c),
+            Ins::Load(width) => {
+                let (_, opcode, _, _, align) = memory_op(width);
+                body.extend([opcode, align, 0]);
+            }
+            Ins::Store(width) => {
+                let (_, _, _, opcode, align) = memory_op(width);
+                body.extend([opcode, align, 0]);
+            }
+        }
+    }
+    body.push(0x0b);
+    unsigned(out, body.len() as u32);
+    out.extend(body);
+}
+
+/// Write a section with its id and size
+fn section(out: &mut Vec<u8>, id: u8, contents: impl FnOnce(&mut Vec<u8>)) {
+    let mut section = Vec::new();
+    contents(&mut section);
+    out.push(id);
+    unsigned(out, section.len() as u32);
+    out.extend(section);
+}
+
+fn name_bytes(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u32);
+    out.extend(name.as_bytes());
+}
+
+/// Unsigned LEB128
+fn unsigned(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Signed LEB128
+fn signed(out: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(bytes: &[u8]) {
+        wasmparser::Validator::new().validate_all(bytes).unwrap();
+    }
+
+    #[test]
+    fn test_leb128() {
+        // Values from the examples in the spec
+        let mut out = Vec::new();
+        unsigned(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+        out.clear();
+        signed(&mut out, -123456);
+        assert_eq!(out, [0xc0, 0xbb, 0x78]);
+        out.clear();
+        signed(&mut out, 64);
+        assert_eq!(out, [0xc0, 0x00]);
+    }
+
+    #[test]
+    fn test_loops_use_block_loop_br_if() {
+        // A loop checks the cell before entering and at the end of each pass
+        let program: BrainfuckProgram = ",[.,]".parse().unwrap();
+        let text = generate_text(&program, &MachineConfig::default()).unwrap();
+        let run = &text[text.find("(func (export").unwrap()..];
+        assert!(run.contains(
+            "
+    block
+      local.get $p
+      call $at
+      i32.load8_u
+      i32.eqz
+      br_if 0
+      loop
+        local.get $p
+        call $at
+        i32.load8_u
+        call $write_byte
+"
+        ));
+        assert!(run.contains("        br_if 0\n      end\n    end\n"));
+    }
+
+    #[test]
+    fn test_text_and_binary_agree() {
+        // Both formats validate and describe the same code
+        for (source, config) in [
+            (
+                include_str!("../../programs/hello_world.bf"),
+                MachineConfig::default(),
+            ),
+            (
+                include_str!("../../programs/rot13.bf"),
+                MachineConfig {
+                    cell_width: CellWidth::U16,
+                    eof: EofBehavior::Zero,
+                    ..Default::default()
+                },
+            ),
+            (
+                "[>]<<,[->+>-<<]",
+                MachineConfig {
+                    cell_width: CellWidth::I32,
+                    eof: EofBehavior::MinusOne,
+                    tape_length: TapeLength::Fixed(100_000),
+                    ..Default::default()
+                },
+            ),
+        ] {
+            let program: BrainfuckProgram = source.parse().unwrap();
+            let binary = generate_binary(&program, &config).unwrap();
+            let text = wat::parse_str(generate_text(&program, &config).unwrap()).unwrap();
+            validate(&binary);
+            validate(&text);
+            // The text version carries extra names, so only compare code
+            assert_eq!(code_section(&binary), code_section(&text));
+        }
+    }
+
+    fn code_section(bytes: &[u8]) -> Vec<u8> {
+        wasmparser::Parser::new(0)
+            .parse_all(bytes)
+            .find_map(|payload| match payload.unwrap() {
+                wasmparser::Payload::CodeSectionStart { range, .. } => Some(bytes[range].to_vec()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_unsupported_config() {
+        // Linear memory has a fixed size here and cells always wrap
+        let program = BrainfuckProgram::default();
+        for config in [
+            MachineConfig {
+                tape_length: TapeLength::Growable,
+                ..Default::default()
+            },
+            MachineConfig {
+                overflow: Overflow::Saturate,
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                generate_binary(&program, &config),
+                Err(CodegenError::Unsupported(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! x86-64 machine code shared by the JIT and the ELF backend
+//!
+//! The [`Encoder`] turns lowered operations into machine code working on
+//! 8-bit wrapping cells on a fixed tape, checking every pointer move and
+//! offset access against the ends of the tape. Only input and output differ
+//! between the JIT and an executable, so those are left to an [`Io`].
+//!
+//! Register use inside the generated code:
+//! - `rbx`: address of the current cell
+//! - `r13`: address of the first cell
+//! - `r14`: address just past the last cell
+
+use crate::ir::Op;
+
+/// An offset or move does not fit into a 32-bit displacement
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct OffsetTooLarge(pub(crate) isize);
+
+/// Emits the code for reading and writing a cell
+pub(crate) trait Io {
+    /// Write the cell at `rbx + offset`, whose address is in `rax`
+    fn output(&self, encoder: &mut Encoder, offset: i32);
+
+    /// Read into the cell at `rbx + offset`, whose address is in `rax`
+    fn input(&self, encoder: &mut Encoder, offset: i32);
+}
+
+/// Point the 32-bit displacement at `at` to `target`, relative to the end
+/// of the displacement like all jumps and `rip` relative addresses
+pub(crate) fn patch(code: &mut [u8], at: usize, target: usize) {
+    let displacement = target as i32 - (at as i32 + 4);
+    code[at..at + 4].copy_from_slice(&displacement.to_le_bytes());
+}
+
+/// Emits x86-64 machine code for lowered operations
+#[derive(Default)]
+pub(crate) struct Encoder {
+    pub(crate) code: Vec<u8>,
+    /// Jumps to the out-of-bounds handler, patched in `patch_exits`
+    fault_jumps: Vec<usize>,
+    /// Jumps to the i/o error handler, patched in `patch_exits`
+    io_jumps: Vec<usize>,
+}
+
+impl Encoder {
+    /// Offset of the next byte
+    pub(crate) fn here(&self) -> usize {
+        self.code.len()
+    }
+
+    pub(crate) fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn emit_i32(&mut self, value: i32) {
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// Emit an instruction ending in a 32-bit displacement to be patched later
+    pub(crate) fn jump(&mut self, opcode: &[u8]) -> usize {
+        self.emit(opcode);
+        self.emit_i32(0);
+        self.code.len() - 4
+    }
+
+    /// Point the displacement at `at` to `target`
+    pub(crate) fn patch(&mut self, at: usize, target: usize) {
+        patch(&mut self.code, at, target);
+    }
+
+    /// Emit a jump to the i/o error handler
+    pub(crate) fn io_error_jump(&mut self, opcode: &[u8]) {
+        let at = self.jump(opcode);
+        self.io_jumps.push(at);
+    }
+
+    /// Point all jumps to the handlers at where they ended up
+    pub(crate) fn patch_exits(&mut self, fault: usize, io: usize) {
+        for at in std::mem::take(&mut self.fault_jumps) {
+            self.patch(at, fault);
+        }
+        for at in std::mem::take(&mut self.io_jumps) {
+            self.patch(at, io);
+        }
+    }
+
+    pub(crate) fn body(&mut self, ops: &[Op], io: &impl Io) -> Result<(), OffsetTooLarge> {
+        for op in ops {
+            match *op {
+                Op::Add { offset, delta } => {
+                    let offset = self.checked_offset(offset)?;
+                    // add byte [rbx + offset], delta
+                    self.emit(&[0x80, 0x83]);
+                    self.emit_i32(offset);
+                    self.emit(&[delta as u8]);
+                }
+                Op::Move(distance) => {
+                    self.checked_offset(distance)?;
+                    self.emit(&[0x48, 0x89, 0xc3]); // mov rbx, rax
+                }
+                Op::SetZero { offset } => {
+                    let offset = self.checked_offset(offset)?;
+                    // mov byte [rbx + offset], 0
+                    self.emit(&[0xc6, 0x83]);
+                    self.emit_i32(offset);
+                    self.emit(&[0]);
+                }
+                Op::AddMul { offset, factor } => {
+                    let offset = self.checked_offset(offset)?;
+                    self.emit(&[0x0f, 0xb6, 0x03]); // movzx eax, byte [rbx]
+                    self.emit(&[0x69, 0xc0]); // imul eax, eax, factor
+                    self.emit_i32(factor);
+                    // add byte [rbx + offset], al
+                    self.emit(&[0x00, 0x83]);
+                    self.emit_i32(offset);
+                }
+                Op::Scan(step) => {
+                    let start = self.here();
+                    self.emit(&[0x80, 0x3b, 0x00]); // cmp byte [rbx], 0
+                    let done = self.jump(&[0x0f, 0x84]); // je done
+                    self.checked_offset(step)?;
+                    self.emit(&[0x48, 0x89, 0xc3]); // mov rbx, rax
+                    let back = self.jump(&[0xe9]); // jmp start
+                    let end = self.here();
+                    self.patch(back, start);
+                    self.patch(done, end);
+                }
+                Op::Output { offset } => {
+                    let offset = self.checked_offset(offset)?;
+                    io.output(self, offset);
+                }
+                Op::Input { offset } => {
+                    let offset = self.checked_offset(offset)?;
+                    io.input(self, offset);
+                }
+                Op::Loop(ref body) => {
+                    self.emit(&[0x80, 0x3b, 0x00]); // cmp byte [rbx], 0
+                    let skip = self.jump(&[0x0f, 0x84]); // je end
+                    let start = self.here();
+                    self.body(body, io)?;
+                    self.emit(&[0x80, 0x3b, 0x00]); // cmp byte [rbx], 0
+                    let back = self.jump(&[0x0f, 0x85]); // jne start
+                    let end = self.here();
+                    self.patch(back, start);
+                    self.patch(skip, end);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load `rbx + offset` into `rax` and bail out if it is off the tape
+    fn checked_offset(&mut self, offset: isize) -> Result<i32, OffsetTooLarge> {
+        let offset = i32::try_from(offset).map_err(|_| OffsetTooLarge(offset))?;
+        // lea rax, [rbx + offset]
+        self.emit(&[0x48, 0x8d, 0x83]);
+        self.emit_i32(offset);
+        self.emit(&[0x4c, 0x39, 0xe8]); // cmp rax, r13
+        let below = self.jump(&[0x0f, 0x82]); // jb fault
+        self.emit(&[0x4c, 0x39, 0xf0]); // cmp rax, r14
+        let above = self.jump(&[0x0f, 0x83]); // jae fault
+        self.fault_jumps.extend([below, above]);
+        Ok(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leaves input and output out
+    struct NoIo;
+
+    impl Io for NoIo {
+        fn output(&self, _encoder: &mut Encoder, _offset: i32) {}
+
+        fn input(&self, _encoder: &mut Encoder, _offset: i32) {}
+    }
+
+    #[test]
+    fn test_loop_jumps() {
+        // Both jumps of an empty loop land right after their partner
+        let mut encoder = Encoder::default();
+        encoder.body(&[Op::Loop(Vec::new())], &NoIo).unwrap();
+        assert_eq!(
+            encoder.code,
+            [
+                0x80, 0x3b, 0x00, 0x0f, 0x84, 9, 0, 0, 0, // cmp, je end
+                0x80, 0x3b, 0x00, 0x0f, 0x85, 0xf7, 0xff, 0xff, 0xff, // cmp, jne start
+            ]
+        );
+    }
+
+    #[test]
+    fn test_offset_too_large() {
+        // Offsets must fit into a displacement
+        let mut encoder = Encoder::default();
+        assert_eq!(
+            encoder.body(&[Op::Move(1 << 40)], &NoIo),
+            Err(OffsetTooLarge(1 << 40))
+        );
+    }
+}
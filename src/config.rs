@@ -0,0 +1,268 @@
+use std::{fmt, str::FromStr};
+
+use crate::DEFAULT_TAPE_LEN;
+
+/// Size and signedness of a single cell
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum CellWidth {
+    #[default]
+    U8,
+    U16,
+    U32,
+    I32,
+}
+
+impl CellWidth {
+    /// Smallest value a cell can hold
+    pub fn min(self) -> i64 {
+        match self {
+            CellWidth::I32 => i32::MIN.into(),
+            _ => 0,
+        }
+    }
+
+    /// Largest value a cell can hold
+    pub fn max(self) -> i64 {
+        match self {
+            CellWidth::U8 => u8::MAX.into(),
+            CellWidth::U16 => u16::MAX.into(),
+            CellWidth::U32 => u32::MAX.into(),
+            CellWidth::I32 => i32::MAX.into(),
+        }
+    }
+
+    /// Number of bits in a cell
+    pub fn bits(self) -> u32 {
+        match self {
+            CellWidth::U8 => 8,
+            CellWidth::U16 => 16,
+            CellWidth::U32 | CellWidth::I32 => 32,
+        }
+    }
+
+    /// Bring any value into range by wrapping around
+    pub fn wrap(self, value: i64) -> i64 {
+        // Cell sizes are powers of two, so wrapping is just masking
+        let mask = (1i64 << self.bits()) - 1;
+        (value.wrapping_sub(self.min()) & mask).wrapping_add(self.min())
+    }
+}
+
+/// What happens when a cell goes past its smallest or largest value
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Overflow {
+    /// Wrap around to the other end
+    #[default]
+    Wrap,
+    /// Stop with [`ExecError::CellOverflow`](crate::ExecError::CellOverflow)
+    Error,
+    /// Stay at the smallest or largest value
+    Saturate,
+}
+
+/// How many cells there are and in which directions the tape can grow
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TapeLength {
+    /// Exactly this many cells, starting at the pointer
+    Fixed(usize),
+    /// Grows to the right as needed, moving left of the start is an error
+    Growable,
+    /// Grows in both directions as needed
+    Unbounded,
+}
+
+impl Default for TapeLength {
+    fn default() -> Self {
+        TapeLength::Fixed(DEFAULT_TAPE_LEN)
+    }
+}
+
+/// What `,` does with the current cell once the input is used up
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum EofBehavior {
+    /// Leave the cell as it is
+    #[default]
+    Unchanged,
+    /// Set the cell to 0
+    Zero,
+    /// Set the cell to -1, i.e. the largest value for unsigned cells
+    MinusOne,
+}
+
+impl FromStr for CellWidth {
+    type Err = String;
+
+    /// Parse `8`, `16`, `32` or `i32`, optionally with a `u` in front
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('u') {
+            "8" => Ok(CellWidth::U8),
+            "16" => Ok(CellWidth::U16),
+            "32" => Ok(CellWidth::U32),
+            "i32" => Ok(CellWidth::I32),
+            _ => Err(format!("unknown cell width `{s}`")),
+        }
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Overflow::Wrap),
+            "error" => Ok(Overflow::Error),
+            "saturate" => Ok(Overflow::Saturate),
+            _ => Err(format!("unknown overflow policy `{s}`")),
+        }
+    }
+}
+
+impl FromStr for TapeLength {
+    type Err = String;
+
+    /// Parse a number of cells, `growable` or `unbounded`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "growable" => Ok(TapeLength::Growable),
+            "unbounded" => Ok(TapeLength::Unbounded),
+            _ => match s.replace('_', "").parse() {
+                Ok(0) | Err(_) => Err(format!("invalid tape length `{s}`")),
+                Ok(len) => Ok(TapeLength::Fixed(len)),
+            },
+        }
+    }
+}
+
+impl FromStr for EofBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(EofBehavior::Unchanged),
+            "zero" | "0" => Ok(EofBehavior::Zero),
+            "minus-one" | "-1" => Ok(EofBehavior::MinusOne),
+            _ => Err(format!("unknown end of input behaviour `{s}`")),
+        }
+    }
+}
+
+/// Prints the names [`FromStr`] accepts
+impl fmt::Display for CellWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CellWidth::I32 => write!(f, "i32"),
+            width => write!(f, "{}", width.bits()),
+        }
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Wrap => write!(f, "wrap"),
+            Overflow::Error => write!(f, "error"),
+            Overflow::Saturate => write!(f, "saturate"),
+        }
+    }
+}
+
+impl fmt::Display for TapeLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TapeLength::Fixed(len) => write!(f, "{len}"),
+            TapeLength::Growable => write!(f, "growable"),
+            TapeLength::Unbounded => write!(f, "unbounded"),
+        }
+    }
+}
+
+impl fmt::Display for EofBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EofBehavior::Unchanged => write!(f, "unchanged"),
+            EofBehavior::Zero => write!(f, "zero"),
+            EofBehavior::MinusOne => write!(f, "minus-one"),
+        }
+    }
+}
+
+/// The machine model a program is run on
+///
+/// The default is the classic model: 30 000 wrapping 8-bit cells, with `,`
+/// leaving the cell unchanged at the end of the input.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct MachineConfig {
+    pub cell_width: CellWidth,
+    pub overflow: Overflow,
+    pub tape_length: TapeLength,
+    pub eof: EofBehavior,
+}
+
+impl MachineConfig {
+    /// Value `,` stores at the end of the input, if any
+    pub fn eof_value(&self) -> Option<i64> {
+        match self.eof {
+            EofBehavior::Unchanged => None,
+            EofBehavior::Zero => Some(0),
+            EofBehavior::MinusOne => Some(self.cell_width.wrap(-1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_width_wrap() {
+        // Wrapping respects both the size and the signedness of cells
+        assert_eq!(CellWidth::U8.wrap(256), 0);
+        assert_eq!(CellWidth::U8.wrap(-1), 255);
+        assert_eq!(CellWidth::U16.wrap(-1), 65_535);
+        assert_eq!(CellWidth::U32.wrap(1 << 32), 0);
+        assert_eq!(
+            CellWidth::I32.wrap(i64::from(i32::MAX) + 1),
+            i32::MIN.into()
+        );
+        assert_eq!(CellWidth::I32.wrap(-1), -1);
+    }
+
+    #[test]
+    fn test_eof_value() {
+        // -1 at the end of input is the largest value of unsigned cells
+        let mut config = MachineConfig {
+            eof: EofBehavior::MinusOne,
+            ..Default::default()
+        };
+        assert_eq!(config.eof_value(), Some(255));
+        config.cell_width = CellWidth::I32;
+        assert_eq!(config.eof_value(), Some(-1));
+        assert_eq!(MachineConfig::default().eof_value(), None);
+    }
+
+    #[test]
+    fn test_from_str() {
+        // Names as used on the command line
+        assert_eq!("16".parse(), Ok(CellWidth::U16));
+        assert_eq!("u8".parse(), Ok(CellWidth::U8));
+        assert_eq!("i32".parse(), Ok(CellWidth::I32));
+        assert!("64".parse::<CellWidth>().is_err());
+        assert_eq!("saturate".parse(), Ok(Overflow::Saturate));
+        assert_eq!("30_000".parse(), Ok(TapeLength::Fixed(30_000)));
+        assert_eq!("unbounded".parse(), Ok(TapeLength::Unbounded));
+        assert!("0".parse::<TapeLength>().is_err());
+        assert_eq!("-1".parse(), Ok(EofBehavior::MinusOne));
+        assert!("eof".parse::<EofBehavior>().is_err());
+        // Printed names parse again
+        for width in [
+            CellWidth::U8,
+            CellWidth::U16,
+            CellWidth::U32,
+            CellWidth::I32,
+        ] {
+            assert_eq!(width.to_string().parse(), Ok(width));
+        }
+        assert_eq!(TapeLength::Growable.to_string(), "growable");
+        assert_eq!(EofBehavior::MinusOne.to_string(), "minus-one");
+    }
+}
@@ -0,0 +1,679 @@
+//! Step debugger over the source of a program
+//!
+//! The [`Debugger`] runs a program one command at a time, where every `[`
+//! and `]` counts as a command of its own, so it can always tell which
+//! character of the source runs next. On top of single steps it can step
+//! over a loop, step out of the loop it is in and run until a breakpoint or
+//! watchpoint stops it.
+//!
+//! Commands run on an [`Interpreter`], which keeps the
+//! [`history`](crate::history) the debugger steps backwards with, so both
+//! always agree on what a program does.
+
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+};
+
+use crate::{
+    history::{Record, DEFAULT_HISTORY_LIMIT},
+    parse_spanned, strip_spans, ExecError, Instruction, Interpreter, MachineConfig, ParseError,
+    Position, SpannedInstruction, Tape,
+};
+
+/// Condition stopping the program once it becomes true
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Watchpoint {
+    /// The cell at a position changes its value
+    CellChanged(isize),
+    /// The cell at a position gets the given value
+    CellValue { position: isize, value: i64 },
+    /// The data pointer arrives at a position
+    Pointer(isize),
+}
+
+impl Watchpoint {
+    /// Whether a command changing the watched value from `before` to `after`
+    /// makes the watchpoint trigger
+    fn triggered(&self, before: i64, after: i64) -> bool {
+        match *self {
+            Watchpoint::CellChanged(_) => before != after,
+            Watchpoint::CellValue { value, .. } => before != value && after == value,
+            Watchpoint::Pointer(position) => before != position as i64 && after == position as i64,
+        }
+    }
+}
+
+/// Why the debugger stopped running the program
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stop {
+    /// The requested step is done
+    Step,
+    /// The next command has a breakpoint
+    Breakpoint(Position),
+    /// The last command made a watchpoint trigger
+    Watchpoint(Watchpoint),
+    /// There is nothing left to run
+    Finished,
+    /// Going backwards reached the oldest recorded command
+    Start,
+    /// The limit of commands given to [`Debugger::pursue`] ran out before
+    /// the goal was reached
+    Limit,
+}
+
+/// Where a command of the debugger stops, so it can be run in parts with
+/// [`Debugger::pursue`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Goal {
+    /// A single command
+    Step,
+    /// Until the next command is in at most this many loops
+    Depth(usize),
+    /// Until a breakpoint, a watchpoint or the end of the program
+    End,
+    /// Backwards until a breakpoint, a watchpoint or the oldest recorded
+    /// command
+    Start,
+}
+
+/// Runs a program command by command on a tape
+pub struct Debugger<R, W> {
+    source: String,
+    spanned: Vec<SpannedInstruction>,
+    program: Vec<Instruction>,
+    interpreter: Interpreter<R, W>,
+    /// Positions of all commands in source order, brackets included
+    commands: Vec<Position>,
+    breakpoints: BTreeSet<Position>,
+    watchpoints: Vec<Watchpoint>,
+    steps: u64,
+}
+
+impl<R: Read, W: Write> Debugger<R, W> {
+    /// Debugger stopped before the first command of `source`
+    pub fn new(
+        source: &str,
+        config: &MachineConfig,
+        input: R,
+        output: W,
+    ) -> Result<Self, ParseError> {
+        let spanned = parse_spanned(source)?;
+        let mut commands = Vec::new();
+        positions(&spanned, &mut commands);
+        let mut interpreter = Interpreter::with_config(config, input, output);
+        interpreter.set_history_limit(DEFAULT_HISTORY_LIMIT);
+        Ok(Debugger {
+            source: source.to_string(),
+            program: strip_spans(&spanned),
+            spanned,
+            interpreter,
+            commands,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            steps: 0,
+        })
+    }
+
+    /// The source being debugged
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The tape in its current state
+    pub fn tape(&self) -> &Tape {
+        self.interpreter.tape()
+    }
+
+    /// The input still to be read
+    pub fn input(&self) -> &R {
+        self.interpreter.input()
+    }
+
+    /// Input given back by commands that were undone, to be read before
+    /// [`Debugger::input`]
+    pub fn replayed_input(&self) -> impl Iterator<Item = u8> + '_ {
+        self.interpreter.recorded().replayed_input()
+    }
+
+    /// Everything written so far, including output of commands that were
+    /// undone since, see [`Debugger::output_len`]
+    pub fn output(&self) -> &W {
+        self.interpreter.output()
+    }
+
+    /// Number of output bytes the commands run so far wrote
+    pub fn output_len(&self) -> usize {
+        self.interpreter.recorded().output_len()
+    }
+
+    /// Give back the tape, input and output
+    pub fn into_parts(self) -> (Tape, R, W) {
+        self.interpreter.into_parts()
+    }
+
+    /// Where the next command is, `None` once the program finished
+    pub fn position(&self) -> Option<Position> {
+        self.locate(self.interpreter.paused())
+    }
+
+    /// Where the command at a path like in [`Debugger::history`] is, the
+    /// end of a loop body standing for its `]`
+    pub fn locate(&self, path: &[usize]) -> Option<Position> {
+        let (&last, outer) = path.split_last().unwrap_or((&0, &[]));
+        let mut body = &self.spanned[..];
+        let mut close = None;
+        for &index in outer {
+            let SpannedInstruction::Loop {
+                body: inner,
+                close: at,
+                ..
+            } = body.get(index)?
+            else {
+                return None;
+            };
+            body = inner;
+            close = Some(*at);
+        }
+        match body.get(last) {
+            Some(instruction) => Some(instruction.position()),
+            None => close,
+        }
+    }
+
+    /// The next command as written in the source
+    pub fn next_command(&self) -> Option<char> {
+        let at = self.position()?;
+        self.source[at.offset..].chars().next()
+    }
+
+    /// Whether every command has run
+    pub fn is_finished(&self) -> bool {
+        self.position().is_none()
+    }
+
+    /// Number of commands run so far, counting brackets
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Number of loops the next command is in
+    pub fn depth(&self) -> usize {
+        self.interpreter.paused().len().saturating_sub(1)
+    }
+
+    /// Stop at the first command at or after the byte `offset` of the
+    /// source, giving back where that command is
+    pub fn add_breakpoint(&mut self, offset: usize) -> Option<Position> {
+        let at = *self.commands.iter().find(|at| at.offset >= offset)?;
+        self.breakpoints.insert(at);
+        Some(at)
+    }
+
+    /// Remove the breakpoint on the command at the byte `offset`, giving back
+    /// whether there was one
+    pub fn remove_breakpoint(&mut self, offset: usize) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|at| at.offset != offset);
+        self.breakpoints.len() != before
+    }
+
+    /// Positions of all breakpoints, in source order
+    pub fn breakpoints(&self) -> Vec<Position> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    /// Add a breakpoint after every `#` in the source, which is a comment
+    /// otherwise
+    pub fn break_on_markers(&mut self) {
+        let markers: Vec<usize> = self.source.match_indices('#').map(|(i, _)| i).collect();
+        for offset in markers {
+            self.add_breakpoint(offset);
+        }
+    }
+
+    /// Stop as soon as a command makes the watchpoint trigger
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove a watchpoint, giving back whether it was there
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != before
+    }
+
+    /// All watchpoints, in the order they were added
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Keep undo records of at most `limit` commands, 0 turning the history
+    /// off
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.interpreter.set_history_limit(limit);
+    }
+
+    /// Undo records of the latest commands, the newest last, each at its
+    /// path like in [`Interpreter::history`], see [`Debugger::locate`]
+    pub fn history(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &Record<Vec<usize>>> + ExactSizeIterator {
+        self.interpreter.history()
+    }
+
+    /// The latest recorded command writing the cell at `position`, with
+    /// its step number counting from 1
+    pub fn last_write(&self, position: isize) -> Option<(u64, Position)> {
+        let (age, record) = self.interpreter.recorded().last_write(position)?;
+        Some((self.steps - age as u64, self.locate(&record.at)?))
+    }
+
+    /// Run a single command
+    pub fn step(&mut self) -> Result<Stop, ExecError> {
+        self.pursue(Goal::Step, u64::MAX)
+    }
+
+    /// Run a whole loop if the next command starts one, otherwise a single
+    /// command
+    pub fn step_over(&mut self) -> Result<Stop, ExecError> {
+        self.pursue(self.over(), u64::MAX)
+    }
+
+    /// Run until the loop around the next command is done, or to the end
+    /// outside of loops
+    pub fn step_out(&mut self) -> Result<Stop, ExecError> {
+        self.pursue(self.out(), u64::MAX)
+    }
+
+    /// Run until a breakpoint, a watchpoint or the end of the program
+    pub fn run(&mut self) -> Result<Stop, ExecError> {
+        self.pursue(Goal::End, u64::MAX)
+    }
+
+    /// Undo the latest command
+    pub fn step_back(&mut self) -> Result<Stop, ExecError> {
+        if self.undo()? {
+            Ok(Stop::Step)
+        } else {
+            Ok(Stop::Start)
+        }
+    }
+
+    /// Undo commands until one with a breakpoint is next, a watchpoint
+    /// triggers going backwards or the history runs out
+    pub fn reverse_continue(&mut self) -> Result<Stop, ExecError> {
+        self.pursue(Goal::Start, u64::MAX)
+    }
+
+    /// Goal of [`Debugger::step_over`] from the next command
+    pub fn over(&self) -> Goal {
+        match self.next_command() {
+            Some('[') => Goal::Depth(self.depth()),
+            _ => Goal::Step,
+        }
+    }
+
+    /// Goal of [`Debugger::step_out`] from the next command
+    pub fn out(&self) -> Goal {
+        match self.depth() {
+            0 => Goal::End,
+            depth => Goal::Depth(depth - 1),
+        }
+    }
+
+    /// Run or undo at most `limit` commands on the way to `goal`
+    ///
+    /// Once the limit runs out this gives back [`Stop::Limit`], and the same
+    /// goal can be pursued further from there. The output is flushed
+    /// afterwards. A failing command is not counted as run, so the debugger
+    /// stays in front of it.
+    pub fn pursue(&mut self, goal: Goal, limit: u64) -> Result<Stop, ExecError> {
+        let result = match goal {
+            Goal::Step => self.forwards(|_| true, limit),
+            Goal::Depth(depth) => self.forwards(|next| next.len() <= depth + 1, limit),
+            Goal::End => self.forwards(|_| false, limit),
+            Goal::Start => self.backwards(limit),
+        };
+        self.interpreter.flush()?;
+        result
+    }
+
+    /// Run at least one command, then until `done` is true for the path of
+    /// the next command or something else stops the program
+    fn forwards(&mut self, done: impl Fn(&[usize]) -> bool, limit: u64) -> Result<Stop, ExecError> {
+        let mut run = 0;
+        while !self.is_finished() {
+            if run > 0 {
+                if done(self.interpreter.paused()) {
+                    return Ok(Stop::Step);
+                }
+                if let Some(at) = self.breakpoint() {
+                    return Ok(Stop::Breakpoint(at));
+                }
+            }
+            // Only stop for the limit once the checks above are done, as
+            // going on skips them
+            if run == limit {
+                return Ok(Stop::Limit);
+            }
+            run += 1;
+            let before = self.watched();
+            self.interpreter.step_once(&self.program)?;
+            self.steps += 1;
+            let after = self.watched();
+            if let Some(watchpoint) = self.triggered(&before, &after) {
+                return Ok(Stop::Watchpoint(watchpoint));
+            }
+        }
+        Ok(Stop::Finished)
+    }
+
+    /// Undo commands until one with a breakpoint is next, a watchpoint
+    /// triggers or the history runs out
+    fn backwards(&mut self, limit: u64) -> Result<Stop, ExecError> {
+        for _ in 0..limit {
+            let after = self.watched();
+            if !self.undo()? {
+                return Ok(Stop::Start);
+            }
+            let before = self.watched();
+            if let Some(watchpoint) = self.triggered(&before, &after) {
+                return Ok(Stop::Watchpoint(watchpoint));
+            }
+            if let Some(at) = self.breakpoint() {
+                return Ok(Stop::Breakpoint(at));
+            }
+        }
+        Ok(Stop::Limit)
+    }
+
+    /// Undo the latest recorded command, giving back whether there was one
+    fn undo(&mut self) -> Result<bool, ExecError> {
+        if !self.interpreter.step_back()? {
+            return Ok(false);
+        }
+        self.steps -= 1;
+        Ok(true)
+    }
+
+    /// The breakpoint on the next command, if it has one
+    fn breakpoint(&self) -> Option<Position> {
+        self.position().filter(|at| self.breakpoints.contains(at))
+    }
+
+    /// The first watchpoint a command taking the watched values from
+    /// `before` to `after` makes trigger
+    fn triggered(&self, before: &[i64], after: &[i64]) -> Option<Watchpoint> {
+        self.watchpoints
+            .iter()
+            .zip(before.iter().zip(after))
+            .find(|(watchpoint, (before, after))| watchpoint.triggered(**before, **after))
+            .map(|(watchpoint, _)| *watchpoint)
+    }
+
+    /// The value each watchpoint looks at
+    fn watched(&self) -> Vec<i64> {
+        let tape = self.tape();
+        self.watchpoints
+            .iter()
+            .map(|watchpoint| match *watchpoint {
+                Watchpoint::CellChanged(position) | Watchpoint::CellValue { position, .. } => {
+                    tape.get_at(position - tape.pointer())
+                }
+                Watchpoint::Pointer(_) => tape.pointer() as i64,
+            })
+            .collect()
+    }
+}
+
+/// Collect where every command of the tree is, brackets included, in
+/// source order
+fn positions(instructions: &[SpannedInstruction], commands: &mut Vec<Position>) {
+    for instruction in instructions {
+        match instruction {
+            SpannedInstruction::Command { at, .. } => commands.push(*at),
+            SpannedInstruction::Loop { body, open, close } => {
+                commands.push(*open);
+                positions(body, commands);
+                commands.push(*close);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(source: &str) -> Debugger<&'static [u8], Vec<u8>> {
+        Debugger::new(source, &MachineConfig::default(), &b"ab"[..], Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_step() {
+        // Every command including brackets is a step of its own, and the
+        // debugger knows where it is in the source
+        let mut debugger = start("+\n[-]");
+        assert_eq!(debugger.next_command(), Some('+'));
+        assert_eq!(debugger.step().unwrap(), Stop::Step);
+        assert_eq!(
+            debugger.position().map(|at| (at.line, at.column)),
+            Some((2, 1))
+        );
+        let mut commands = String::new();
+        while let Some(command) = debugger.next_command() {
+            commands.push(command);
+            debugger.step().unwrap();
+        }
+        assert_eq!(commands, "[-]");
+        assert_eq!(debugger.step().unwrap(), Stop::Finished);
+        assert_eq!(debugger.steps(), 4);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        // Whole loops run as one step, from the inside to their end
+        let mut debugger = start("++[>+++[>+<-]<-]>>.");
+        debugger.step().unwrap();
+        debugger.step().unwrap();
+        assert_eq!(debugger.next_command(), Some('['));
+        assert_eq!(debugger.step_over().unwrap(), Stop::Step);
+        assert_eq!(debugger.position().unwrap().offset, 16);
+        assert_eq!(debugger.tape().cells()[..3], [0, 0, 6]);
+
+        let mut debugger = start("++[>+++[>+<-]<-]>>.");
+        while debugger.depth() < 2 {
+            debugger.step().unwrap();
+        }
+        assert_eq!(debugger.step_out().unwrap(), Stop::Step);
+        assert_eq!(debugger.depth(), 1);
+        assert_eq!(debugger.next_command(), Some('<'));
+        assert_eq!(debugger.tape().cells()[..3], [2, 0, 3]);
+        assert_eq!(debugger.step_out().unwrap(), Stop::Step);
+        assert_eq!(debugger.step_out().unwrap(), Stop::Finished);
+        assert_eq!(debugger.output(), &[6]);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // Breakpoints by offset and by marker, which also stop inside loops
+        let mut debugger = start("+++[>+<-] # >.");
+        assert_eq!(debugger.add_breakpoint(5).map(|at| at.offset), Some(5));
+        assert_eq!(debugger.add_breakpoint(100), None);
+        debugger.break_on_markers();
+        assert_eq!(debugger.breakpoints().len(), 2);
+        for _ in 0..3 {
+            assert!(matches!(debugger.run().unwrap(), Stop::Breakpoint(at) if at.offset == 5));
+        }
+        assert!(debugger.remove_breakpoint(5));
+        assert!(matches!(debugger.run().unwrap(), Stop::Breakpoint(at) if at.offset == 12));
+        assert_eq!(debugger.run().unwrap(), Stop::Finished);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // Watchpoints trigger on the command that makes them true
+        let mut debugger = start(",>+++>,<<[-]");
+        debugger.add_watchpoint(Watchpoint::CellValue {
+            position: 1,
+            value: 2,
+        });
+        debugger.add_watchpoint(Watchpoint::Pointer(2));
+        debugger.add_watchpoint(Watchpoint::CellChanged(0));
+        let stop = debugger.run().unwrap();
+        assert_eq!(stop, Stop::Watchpoint(Watchpoint::CellChanged(0)));
+        assert_eq!(debugger.tape().get(), i64::from(b'a'));
+        let stop = debugger.run().unwrap();
+        assert_eq!(
+            stop,
+            Stop::Watchpoint(Watchpoint::CellValue {
+                position: 1,
+                value: 2
+            })
+        );
+        assert_eq!(
+            debugger.run().unwrap(),
+            Stop::Watchpoint(Watchpoint::Pointer(2))
+        );
+        assert!(debugger.remove_watchpoint(Watchpoint::CellChanged(0)));
+        assert_eq!(debugger.run().unwrap(), Stop::Finished);
+    }
+
+    #[test]
+    fn test_errors_stay_put() {
+        // A failing command can be looked at and fails again
+        let mut debugger = start("+<");
+        assert!(matches!(
+            debugger.run(),
+            Err(ExecError::PointerOutOfBounds { .. })
+        ));
+        assert_eq!(debugger.next_command(), Some('<'));
+        assert_eq!(debugger.steps(), 1);
+        assert!(debugger.step().is_err());
+    }
+
+    #[test]
+    fn test_step_back() {
+        // Going back restores cells, pointer, input and output, and going
+        // forwards again does the same as the first time
+        let mut debugger = start(",>,.<[->+<]>.");
+        debugger.run().unwrap();
+        let tape = debugger.tape().clone();
+        assert_eq!(debugger.output(), b"b\xc3");
+        while debugger.step_back().unwrap() == Stop::Step {}
+        assert_eq!(debugger.steps(), 0);
+        assert_eq!(debugger.tape(), &Tape::default());
+        assert_eq!(debugger.replayed_input().collect::<Vec<_>>(), b"ab");
+        assert_eq!(debugger.output_len(), 0);
+        assert_eq!(debugger.run().unwrap(), Stop::Finished);
+        assert_eq!(debugger.tape(), &tape);
+        assert_eq!(debugger.output(), b"b\xc3");
+        assert_eq!(debugger.output_len(), 2);
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        // Going back stops at breakpoints and at the command that made a
+        // watchpoint trigger
+        let mut debugger = start("++[>+<-]>>+");
+        debugger.add_breakpoint(3);
+        debugger.add_watchpoint(Watchpoint::CellValue {
+            position: 1,
+            value: 1,
+        });
+        while !debugger.is_finished() {
+            debugger.step().unwrap();
+        }
+        assert!(
+            matches!(debugger.reverse_continue().unwrap(), Stop::Breakpoint(at) if at.offset == 3)
+        );
+        assert_eq!(debugger.tape().cells()[..2], [1, 1]);
+        let stop = debugger.reverse_continue().unwrap();
+        assert_eq!(
+            stop,
+            Stop::Watchpoint(Watchpoint::CellValue {
+                position: 1,
+                value: 1
+            })
+        );
+        assert_eq!(debugger.next_command(), Some('+'));
+        assert_eq!(debugger.tape().cells()[..2], [2, 0]);
+        debugger.remove_breakpoint(3);
+        assert_eq!(debugger.reverse_continue().unwrap(), Stop::Start);
+        assert_eq!(debugger.steps(), 0);
+    }
+
+    #[test]
+    fn test_last_write() {
+        // The latest command writing a cell, found in the history
+        let mut debugger = start("+>++\n<-");
+        debugger.run().unwrap();
+        assert_eq!(
+            debugger
+                .last_write(0)
+                .map(|(step, at)| (step, at.line, at.column)),
+            Some((6, 2, 2))
+        );
+        assert_eq!(debugger.last_write(1).map(|(step, _)| step), Some(4));
+        assert_eq!(debugger.last_write(2), None);
+        debugger.set_history_limit(2);
+        assert_eq!(debugger.last_write(1), None);
+        assert_eq!(debugger.history().len(), 2);
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        // Stepping ends where the interpreter does on any machine, and going
+        // back over the end of a loop stops at its `]`
+        let source = "+[->-[>+<-]<],[->>+<<]>>.";
+        let config = MachineConfig {
+            overflow: crate::Overflow::Saturate,
+            eof: crate::EofBehavior::MinusOne,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_config(&config, &b""[..], Vec::new());
+        interpreter.run(&source.parse().unwrap()).unwrap();
+        let mut debugger = Debugger::new(source, &config, &b""[..], Vec::new()).unwrap();
+        while debugger.step().unwrap() != Stop::Finished {}
+        assert_eq!(debugger.tape(), interpreter.tape());
+        assert_eq!(debugger.output(), interpreter.output());
+        while debugger.next_command() != Some(',') {
+            debugger.step_back().unwrap();
+        }
+        debugger.step_back().unwrap();
+        assert_eq!(debugger.next_command(), Some(']'));
+        assert_eq!(debugger.depth(), 1);
+    }
+
+    #[test]
+    fn test_pursue_in_parts() {
+        // Running a goal a command at a time stops where running it at once
+        // does, and never skips a breakpoint
+        let source = "++[>+++[>+<-]<-]>>.";
+        let mut whole = start(source);
+        let mut parts = start(source);
+        for debugger in [&mut whole, &mut parts] {
+            debugger.add_breakpoint(9);
+            debugger.step().unwrap();
+            debugger.step().unwrap();
+        }
+        let goals = [whole.over(), Goal::End, Goal::End, Goal::End, Goal::Start];
+        assert_eq!(goals[0], Goal::Depth(0));
+        for goal in goals {
+            let expected = whole.pursue(goal, u64::MAX).unwrap();
+            let mut limits = 0;
+            let stop = loop {
+                match parts.pursue(goal, 1).unwrap() {
+                    Stop::Limit => limits += 1,
+                    stop => break stop,
+                }
+            };
+            assert_eq!(stop, expected);
+            assert!(limits > 0);
+            assert_eq!(parts.steps(), whole.steps());
+            assert_eq!(parts.tape(), whole.tape());
+        }
+    }
+}
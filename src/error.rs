@@ -0,0 +1,236 @@
+use std::fmt;
+
+use crate::{ParseMode, Position, COMMANDS, MAX_NESTING};
+
+/// Everything that can go wrong while parsing brainfuck code
+///
+/// Each variant keeps a copy of the source line it points at, so that the
+/// error can be displayed without access to the original source.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    /// A `[` without a matching `]`
+    UnmatchedOpen { at: Position, source_line: String },
+    /// A `]` without a matching `[`
+    UnmatchedClose { at: Position, source_line: String },
+    /// A non-command character while parsing in [`ParseMode::Strict`]
+    UnexpectedChar {
+        ch: char,
+        at: Position,
+        source_line: String,
+    },
+    /// A `[` nesting loops deeper than [`MAX_NESTING`]
+    TooDeep { at: Position, source_line: String },
+}
+
+impl ParseError {
+    /// Position the error points at
+    pub fn position(&self) -> Position {
+        match self {
+            ParseError::UnmatchedOpen { at, .. }
+            | ParseError::UnmatchedClose { at, .. }
+            | ParseError::UnexpectedChar { at, .. }
+            | ParseError::TooDeep { at, .. } => *at,
+        }
+    }
+
+    /// The source line containing the error
+    pub fn source_line(&self) -> &str {
+        match self {
+            ParseError::UnmatchedOpen { source_line, .. }
+            | ParseError::UnmatchedClose { source_line, .. }
+            | ParseError::UnexpectedChar { source_line, .. }
+            | ParseError::TooDeep { source_line, .. } => source_line,
+        }
+    }
+
+    /// Find out why `source` failed to parse in the given mode
+    ///
+    /// Brackets are matched up in a single pass; the first stray `]`,
+    /// unexpected character or `[` nested too deeply wins, otherwise the
+    /// innermost unclosed `[` is reported.
+    pub(crate) fn diagnose(source: &str, mode: ParseMode) -> ParseError {
+        let mut open = Vec::new();
+        for (offset, ch) in source.char_indices() {
+            match ch {
+                '[' if open.len() == MAX_NESTING => {
+                    let (at, source_line) = locate(source, offset);
+                    return ParseError::TooDeep { at, source_line };
+                }
+                '[' => open.push(offset),
+                // A matched `]` pops its `[` in the guard and falls through
+                ']' if open.pop().is_none() => {
+                    let (at, source_line) = locate(source, offset);
+                    return ParseError::UnmatchedClose { at, source_line };
+                }
+                ch if mode == ParseMode::Strict && !COMMANDS.contains(ch) => {
+                    let (at, source_line) = locate(source, offset);
+                    return ParseError::UnexpectedChar {
+                        ch,
+                        at,
+                        source_line,
+                    };
+                }
+                _ => {}
+            }
+        }
+        match open.pop() {
+            Some(offset) => {
+                let (at, source_line) = locate(source, offset);
+                ParseError::UnmatchedOpen { at, source_line }
+            }
+            None => unreachable!("source failed to parse but has no error"),
+        }
+    }
+}
+
+/// Position of the byte `offset` in `source` and the line containing it
+fn locate(source: &str, offset: usize) -> (Position, String) {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+    let position = Position {
+        offset,
+        line: source[..offset].matches('\n').count() + 1,
+        column: source[line_start..offset].chars().count() + 1,
+    };
+    let line = source[line_start..line_end].trim_end_matches('\r');
+    (position, line.to_string())
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnmatchedOpen { .. } => write!(f, "unmatched `[`")?,
+            ParseError::UnmatchedClose { .. } => write!(f, "unmatched `]`")?,
+            ParseError::UnexpectedChar { ch, .. } => write!(f, "unexpected character {ch:?}")?,
+            ParseError::TooDeep { .. } => write!(f, "loops nested more than {MAX_NESTING} deep")?,
+        }
+        let at = self.position();
+        writeln!(f, " at line {}, column {}", at.line, at.column)?;
+        writeln!(f, "{}", self.source_line())?;
+        // Keep tabs so the caret lines up with the character above it
+        let padding: String = self
+            .source_line()
+            .chars()
+            .take(at.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{padding}^")
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Everything that can go wrong while running a brainfuck program
+#[derive(Debug)]
+pub enum ExecError {
+    /// The data pointer left the tape
+    PointerOutOfBounds { pointer: isize },
+    /// A cell went out of range with [`Overflow::Error`](crate::Overflow::Error)
+    CellOverflow { position: isize },
+    /// Reading input or writing output failed
+    Io(std::io::Error),
+    /// The fuel of the [`Limits`](crate::Limits) ran out
+    OutOfFuel,
+    /// The tape would have to grow past its limit to reach a cell
+    TapeLimit { position: isize },
+    /// The deadline of the [`Limits`](crate::Limits) passed
+    Timeout,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::PointerOutOfBounds { pointer } => {
+                write!(f, "data pointer moved off the tape to cell {pointer}")
+            }
+            ExecError::CellOverflow { position } => write!(f, "cell {position} overflowed"),
+            ExecError::Io(e) => write!(f, "i/o error: {e}"),
+            ExecError::OutOfFuel => write!(f, "ran out of fuel"),
+            ExecError::TapeLimit { position } => {
+                write!(f, "tape would grow past its limit to reach cell {position}")
+            }
+            ExecError::Timeout => write!(f, "ran past the deadline"),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExecError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ExecError {
+    fn from(e: std::io::Error) -> Self {
+        ExecError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_strict};
+
+    #[test]
+    fn test_unmatched_open() {
+        // The innermost unclosed loop is reported
+        let err = parse("+[\n-[[-]").unwrap_err();
+        assert!(matches!(err, ParseError::UnmatchedOpen { .. }));
+        assert_eq!(err.position().offset, 4);
+        assert_eq!((err.position().line, err.position().column), (2, 2));
+    }
+
+    #[test]
+    fn test_unmatched_close() {
+        // A stray `]` is reported even when loops before it are fine
+        let err = parse("[-]\n  +]").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::UnmatchedClose {
+                at: Position {
+                    offset: 7,
+                    line: 2,
+                    column: 4
+                },
+                source_line: "  +]".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unexpected_char() {
+        // Only strict mode complains about comments
+        let err = parse_strict("+[-x]").unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedChar { ch: 'x', .. }));
+        assert_eq!(err.position().column, 4);
+        assert!(parse("+[-x]").is_ok());
+    }
+
+    #[test]
+    fn test_too_deep() {
+        // The first `[` past the limit is reported, unless a stray `]` comes
+        // before it
+        let deep = format!("+\n{}", "[".repeat(MAX_NESTING + 2));
+        let err = parse(&deep).unwrap_err();
+        assert!(matches!(err, ParseError::TooDeep { .. }));
+        assert_eq!(err.position().column, MAX_NESTING + 1);
+        assert!(err.to_string().starts_with("loops nested more than"));
+        let err = parse(&format!("]{deep}")).unwrap_err();
+        assert!(matches!(err, ParseError::UnmatchedClose { .. }));
+    }
+
+    #[test]
+    fn test_display_caret() {
+        // The caret points at the offending character
+        let err = parse("+\n\t++]--").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unmatched `]` at line 2, column 4\n\t++]--\n\t  ^"
+        );
+    }
+}
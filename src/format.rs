@@ -0,0 +1,224 @@
+//! Pretty printing programs as readable source
+//!
+//! Parsing drops all layout, so the formatter lays the instructions out
+//! again from scratch: loop bodies are indented one level per loop and lines
+//! are wrapped at a maximum width. The output only depends on the
+//! instructions, which makes formatting idempotent.
+
+use crate::{BrainfuckProgram, Instruction};
+
+/// How [`format`] lays out a program
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FormatOptions {
+    /// Spaces per loop level
+    pub indent: usize,
+    /// Longest line, including indentation; a line always holds at least
+    /// one command even if that exceeds the width
+    pub max_width: usize,
+    /// Separate runs of the same command with spaces and split them into
+    /// groups of this size, e.g. `++++ ++++ >`
+    pub group: Option<usize>,
+    /// Put every `[` and `]` on a line of its own
+    pub brackets_on_own_lines: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent: 2,
+            max_width: 80,
+            group: None,
+            brackets_on_own_lines: false,
+        }
+    }
+}
+
+/// Format `program` as source code, ending with a newline unless it is empty
+pub fn format(program: &BrainfuckProgram, options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        options,
+        out: String::new(),
+        line: String::new(),
+        depth: 0,
+    };
+    formatter.body(program);
+    formatter.flush();
+    formatter.out
+}
+
+/// Collects words into lines
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    out: String,
+    /// The line being filled, empty or starting with its indentation
+    line: String,
+    depth: usize,
+}
+
+impl Formatter<'_> {
+    fn body(&mut self, instructions: &[Instruction]) {
+        let mut rest = instructions;
+        while let Some(first) = rest.first() {
+            if let Instruction::Loop(body) = first {
+                self.loop_(body);
+                rest = &rest[1..];
+                continue;
+            }
+            let run = rest.iter().take_while(|i| *i == first).count();
+            let command = first.to_string();
+            match self.options.group {
+                Some(size) => {
+                    for start in (0..run).step_by(size.max(1)) {
+                        let len = size.max(1).min(run - start);
+                        self.word(&command.repeat(len));
+                    }
+                }
+                None => {
+                    for _ in 0..run {
+                        self.word(&command);
+                    }
+                }
+            }
+            rest = &rest[run..];
+        }
+    }
+
+    fn loop_(&mut self, body: &[Instruction]) {
+        if body.is_empty() {
+            self.word("[]");
+            return;
+        }
+        if self.options.brackets_on_own_lines {
+            self.flush();
+        }
+        self.word("[");
+        self.flush();
+        self.depth += 1;
+        self.body(body);
+        self.flush();
+        self.depth -= 1;
+        self.word("]");
+        if self.options.brackets_on_own_lines {
+            self.flush();
+        }
+    }
+
+    /// Append a word, starting a new line if it does not fit
+    fn word(&mut self, word: &str) {
+        let separator = usize::from(self.options.group.is_some());
+        if !self.line.is_empty()
+            && self.line.len() + separator + word.len() > self.options.max_width
+        {
+            self.flush();
+        }
+        if self.line.is_empty() {
+            self.line = " ".repeat(self.depth * self.options.indent);
+        } else if self.options.group.is_some() {
+            self.line.push(' ');
+        }
+        self.line.push_str(word);
+    }
+
+    /// Finish the current line, if anything was written to it
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.out.push_str(&self.line);
+            self.out.push('\n');
+            self.line.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_str(source: &str, options: &FormatOptions) -> String {
+        format(&source.parse().unwrap(), options)
+    }
+
+    #[test]
+    fn test_indentation() {
+        // Every loop body goes one level deeper, code after a loop follows
+        // its closing bracket
+        assert_eq!(
+            format_str("++[>+[-]<-]>.", &FormatOptions::default()),
+            "++[\n  >+[\n    -\n  ]<-\n]>.\n"
+        );
+        assert_eq!(format_str("+[]", &FormatOptions::default()), "+[]\n");
+        assert_eq!(format_str("", &FormatOptions::default()), "");
+    }
+
+    #[test]
+    fn test_max_width() {
+        // Long runs wrap, even inside loops, and a word is never split
+        let options = FormatOptions {
+            max_width: 6,
+            ..Default::default()
+        };
+        assert_eq!(format_str("++++++++", &options), "++++++\n++\n");
+        assert_eq!(format_str("[++++++]", &options), "[\n  ++++\n  ++\n]\n");
+        let options = FormatOptions {
+            max_width: 6,
+            group: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(format_str("++++++++", &options), "++++++++\n");
+    }
+
+    #[test]
+    fn test_group_runs() {
+        // Runs are split into groups and separated by spaces
+        let options = FormatOptions {
+            group: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_str("++++++++++>>-.", &options),
+            "++++ ++++ ++ >> - .\n"
+        );
+    }
+
+    #[test]
+    fn test_brackets_on_own_lines() {
+        // Neither the code before a loop nor after it shares a bracket's line
+        let options = FormatOptions {
+            brackets_on_own_lines: true,
+            ..Default::default()
+        };
+        assert_eq!(format_str("+[->+<]>.", &options), "+\n[\n  ->+<\n]\n>.\n");
+    }
+
+    #[test]
+    fn test_idempotent() {
+        // Formatting formatted code changes nothing
+        let sources = [
+            include_str!("../programs/hello_world.bf"),
+            include_str!("../programs/rot13.bf"),
+            include_str!("../programs/nested_loops.bf"),
+        ];
+        let options = [
+            FormatOptions::default(),
+            FormatOptions {
+                indent: 4,
+                max_width: 20,
+                group: Some(5),
+                brackets_on_own_lines: true,
+            },
+            FormatOptions {
+                max_width: 1,
+                ..Default::default()
+            },
+        ];
+        for source in sources {
+            for options in &options {
+                let formatted = format_str(source, options);
+                assert_eq!(format_str(&formatted, options), formatted);
+                assert_eq!(
+                    formatted.parse::<BrainfuckProgram>(),
+                    source.parse::<BrainfuckProgram>()
+                );
+            }
+        }
+    }
+}
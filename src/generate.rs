@@ -0,0 +1,182 @@
+//! Generating programs that print given bytes
+//!
+//! The generated programs assume 8-bit wrapping cells, as in the default
+//! [`MachineConfig`](crate::MachineConfig).
+
+use crate::{BrainfuckProgram, Instruction};
+
+/// Largest factor tried for the setup loop
+const MAX_FACTOR: u32 = 20;
+
+/// How far a byte may be from an existing cell before it gets its own
+const TOLERANCES: [u32; 6] = [0, 3, 6, 10, 15, 25];
+
+/// Generate a short program printing `bytes`
+///
+/// A setup loop first fills a few cells with multiples of a common factor
+/// close to the bytes. Each byte is then printed from whichever cell is
+/// cheapest to reach and adjust, and that cell keeps the byte for later
+/// ones. Different factors and numbers of cells are tried, as is skipping
+/// the setup loop, and the shortest program wins. Its length is
+/// [`BrainfuckProgram::source_len`].
+pub fn print_bytes(bytes: &[u8]) -> BrainfuckProgram {
+    let mut best = Printer::new(Vec::new(), vec![0]).print(bytes);
+    for factor in 2..=MAX_FACTOR {
+        for tolerance in TOLERANCES {
+            let candidate = with_setup(bytes, factor, tolerance);
+            if candidate.source_len() < best.source_len() {
+                best = candidate;
+            }
+        }
+    }
+    best
+}
+
+/// Generate a short program printing `text`, see [`print_bytes`]
+pub fn print_str(text: &str) -> BrainfuckProgram {
+    print_bytes(text.as_bytes())
+}
+
+/// Print `bytes` after a loop setting up cells with multiples of `factor`,
+/// adding a cell for every byte further than `tolerance` from all others
+fn with_setup(bytes: &[u8], factor: u32, tolerance: u32) -> BrainfuckProgram {
+    let mut multiples: Vec<u32> = Vec::new();
+    for &byte in bytes {
+        let byte = u32::from(byte);
+        let multiple = (byte + factor / 2) / factor;
+        let close = multiples
+            .iter()
+            .any(|m| (m * factor).abs_diff(byte) <= tolerance);
+        if multiple != 0 && !close {
+            multiples.push(multiple);
+        }
+    }
+
+    // The counter in cell 0 runs down to zero, the other cells count up
+    let mut body = Vec::new();
+    for &multiple in &multiples {
+        body.push(Instruction::RightShift);
+        body.extend(repeat(Instruction::Increment, multiple));
+    }
+    body.extend(repeat(Instruction::LeftShift, multiples.len() as u32));
+    body.push(Instruction::Decrement);
+    let mut setup = repeat(Instruction::Increment, factor);
+    setup.push(Instruction::Loop(body));
+
+    let mut values = vec![0];
+    values.extend(multiples.iter().map(|m| (m * factor % 256) as u8));
+    Printer::new(setup, values).print(bytes)
+}
+
+fn repeat(instruction: Instruction, times: u32) -> Vec<Instruction> {
+    vec![instruction; times as usize]
+}
+
+/// Prints bytes greedily from a set of cells with known values
+struct Printer {
+    instructions: Vec<Instruction>,
+    values: Vec<u8>,
+    pointer: usize,
+}
+
+impl Printer {
+    fn new(instructions: Vec<Instruction>, values: Vec<u8>) -> Self {
+        Printer {
+            instructions,
+            values,
+            pointer: 0,
+        }
+    }
+
+    fn print(mut self, bytes: &[u8]) -> BrainfuckProgram {
+        for &byte in bytes {
+            let cell = (0..self.values.len())
+                .min_by_key(|&cell| self.cost(cell, byte))
+                .expect("there is always at least one cell");
+
+            let shift = if cell > self.pointer {
+                Instruction::RightShift
+            } else {
+                Instruction::LeftShift
+            };
+            let distance = self.pointer.abs_diff(cell) as u32;
+            self.instructions.extend(repeat(shift, distance));
+            self.pointer = cell;
+
+            let delta = adjustment(self.values[cell], byte);
+            let step = if delta > 0 {
+                Instruction::Increment
+            } else {
+                Instruction::Decrement
+            };
+            self.instructions.extend(repeat(step, delta.unsigned_abs()));
+            self.values[cell] = byte;
+            self.instructions.push(Instruction::Output);
+        }
+        BrainfuckProgram::new(self.instructions)
+    }
+
+    /// Number of commands to get to `cell` and turn it into `byte`
+    fn cost(&self, cell: usize, byte: u8) -> usize {
+        self.pointer.abs_diff(cell) + adjustment(self.values[cell], byte).unsigned_abs() as usize
+    }
+}
+
+/// The shortest change turning `from` into `to` on a wrapping cell
+fn adjustment(from: u8, to: u8) -> i32 {
+    let up = i32::from(to.wrapping_sub(from));
+    if up <= 128 {
+        up
+    } else {
+        up - 256
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter;
+
+    /// Run a program without input and collect what it prints
+    fn output(program: &BrainfuckProgram) -> Vec<u8> {
+        let mut output = Vec::new();
+        interpreter::run(program, &[][..], &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Whatever goes in comes back out of the interpreter
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        let inputs: [&[u8]; 5] = [b"", b"A", b"Hello, World!\n", b"\0\xff\x80\x7f", &all_bytes];
+        for bytes in inputs {
+            assert_eq!(output(&print_bytes(bytes)), bytes);
+        }
+    }
+
+    #[test]
+    fn test_uses_multiplication_loop() {
+        // Text far from zero starts with a setup loop, which pays off
+        let text = b"Hello, World!\n";
+        let program = print_bytes(text);
+        let naive = Printer::new(Vec::new(), vec![0]).print(text);
+        assert!(program
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Loop(_))));
+        assert!(program.source_len() < naive.source_len() / 2, "{program}");
+    }
+
+    #[test]
+    fn test_reuses_cells() {
+        // Repeating a byte only prints the cell again
+        let once = print_str("x").source_len();
+        let many = print_str(&"x".repeat(100)).source_len();
+        assert_eq!(many, once + 99);
+    }
+
+    #[test]
+    fn test_small_values_skip_setup() {
+        // No loop is worth it for bytes close to zero
+        assert_eq!(print_bytes(b"\x02\x01").to_string(), "++.-.");
+    }
+}
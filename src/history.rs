@@ -0,0 +1,225 @@
+//! Execution history for going backwards
+//!
+//! Engines recording their history keep a [`Record`] of how to undo each
+//! instruction they run: the cell it wrote, where the pointer was and the
+//! input it read. Input read by undone instructions is read again from the
+//! records, and output they wrote is not written a second time, so running
+//! forwards after going back does exactly the same as the first time.
+//!
+//! The [`Interpreter`](crate::Interpreter) records when given a history
+//! limit, the [`Debugger`](crate::debugger::Debugger) always does.
+
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+};
+
+use crate::{interpreter::read_byte, ExecError, Tape};
+
+/// Records the debugger keeps by default, older ones are dropped
+pub const DEFAULT_HISTORY_LIMIT: usize = 1 << 20;
+
+/// How to undo an instruction that ran
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Record<L> {
+    /// Where the instruction is in the program
+    pub at: L,
+    /// The cell the instruction wrote and its value before
+    pub write: Option<(isize, i64)>,
+    /// Where the pointer was before, if the instruction moved it
+    pub moved_from: Option<isize>,
+    /// The byte the instruction read
+    pub input: Option<u8>,
+    /// Whether the instruction wrote a byte of output
+    pub output: bool,
+}
+
+impl<L> Record<L> {
+    /// A record of an instruction without any effect yet
+    pub(crate) fn new(at: L) -> Self {
+        Record {
+            at,
+            write: None,
+            moved_from: None,
+            input: None,
+            output: false,
+        }
+    }
+}
+
+/// Records of the latest instructions, the newest last, together with what
+/// undoing them gave back
+#[derive(Debug)]
+pub(crate) struct History<L> {
+    records: VecDeque<Record<L>>,
+    limit: usize,
+    /// Input given back by undone instructions, the next byte to read last
+    replay_input: Vec<u8>,
+    /// Bytes written to the output, including ones of undone instructions
+    written: usize,
+    /// Bytes of output written by undone instructions, not to be written
+    /// again
+    rewound: usize,
+}
+
+impl<L> History<L> {
+    /// A history keeping at most `limit` records, 0 recording nothing
+    pub(crate) fn new(limit: usize) -> Self {
+        History {
+            records: VecDeque::new(),
+            limit,
+            replay_input: Vec::new(),
+            written: 0,
+            rewound: 0,
+        }
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.limit > 0
+    }
+
+    /// Keep at most `limit` records, dropping the oldest ones
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.records.len() > limit {
+            self.records.pop_front();
+        }
+    }
+
+    pub(crate) fn records(&self) -> &VecDeque<Record<L>> {
+        &self.records
+    }
+
+    pub(crate) fn push(&mut self, record: Record<L>) {
+        if !self.is_recording() {
+            return;
+        }
+        if self.records.len() == self.limit {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Forget all records, keeping the input they gave back
+    pub(crate) fn clear(&mut self) {
+        self.records.clear();
+        self.written -= self.rewound;
+        self.rewound = 0;
+    }
+
+    /// Read a byte, input given back by undone instructions first
+    pub(crate) fn read(&mut self, input: &mut impl Read) -> Result<Option<u8>, ExecError> {
+        match self.replay_input.pop() {
+            Some(byte) => Ok(Some(byte)),
+            None => read_byte(input),
+        }
+    }
+
+    /// Write a byte unless an undone instruction already wrote it
+    pub(crate) fn write(&mut self, output: &mut impl Write, byte: u8) -> Result<(), ExecError> {
+        if self.rewound > 0 {
+            self.rewound -= 1;
+        } else {
+            output.write_all(&[byte])?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    /// Input given back by undone instructions, in the order it is read
+    pub(crate) fn replayed_input(&self) -> impl Iterator<Item = u8> + '_ {
+        self.replay_input.iter().rev().copied()
+    }
+
+    /// Number of output bytes written by the instructions that were not
+    /// undone
+    pub(crate) fn output_len(&self) -> usize {
+        self.written - self.rewound
+    }
+
+    /// Undo the latest recorded instruction on `tape`, giving back its record
+    pub(crate) fn undo(&mut self, tape: &mut Tape) -> Result<Option<Record<L>>, ExecError> {
+        let Some(record) = self.records.pop_back() else {
+            return Ok(None);
+        };
+        if let Some((position, value)) = record.write {
+            tape.set_at(position - tape.pointer(), value)?;
+        }
+        if let Some(pointer) = record.moved_from {
+            tape.move_by(pointer - tape.pointer())?;
+        }
+        if let Some(byte) = record.input {
+            self.replay_input.push(byte);
+        }
+        if record.output {
+            self.rewound += 1;
+        }
+        Ok(Some(record))
+    }
+
+    /// The latest record of an instruction writing the cell at `position`,
+    /// with the number of records after it
+    pub(crate) fn last_write(&self, position: isize) -> Option<(usize, &Record<L>)> {
+        self.records
+            .iter()
+            .rev()
+            .enumerate()
+            .find(|(_, record)| matches!(record.write, Some((cell, _)) if cell == position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_replays_input_and_output() {
+        // Undone input is read again and undone output is not written twice
+        let mut history = History::new(2);
+        let mut tape = Tape::default();
+        let mut output = Vec::new();
+        let byte = history.read(&mut &b"a"[..]).unwrap();
+        tape.store_input(0, byte).unwrap();
+        history.push(Record {
+            write: Some((0, 0)),
+            input: byte,
+            ..Record::new(0)
+        });
+        history.write(&mut output, b'a').unwrap();
+        history.push(Record {
+            output: true,
+            ..Record::new(1)
+        });
+        history.undo(&mut tape).unwrap();
+        assert_eq!(
+            history.undo(&mut tape).unwrap().map(|record| record.at),
+            Some(0)
+        );
+        assert_eq!(tape.get(), 0);
+        assert_eq!(history.output_len(), 0);
+        assert_eq!(history.replayed_input().collect::<Vec<_>>(), b"a");
+        assert_eq!(history.read(&mut &b""[..]).unwrap(), Some(b'a'));
+        history.write(&mut output, b'a').unwrap();
+        assert_eq!(output, b"a");
+        assert_eq!(history.output_len(), 1);
+        assert!(history.undo(&mut tape).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_limit() {
+        // The oldest records go first, none are kept without a limit
+        let mut history = History::new(2);
+        for at in 0..3 {
+            history.push(Record {
+                write: Some((at, 0)),
+                ..Record::new(at)
+            });
+        }
+        assert_eq!(history.records().len(), 2);
+        assert!(history.last_write(0).is_none());
+        assert_eq!(history.last_write(1).map(|(age, _)| age), Some(1));
+        history.set_limit(0);
+        history.push(Record::new(3));
+        assert!(history.records().is_empty());
+    }
+}
@@ -0,0 +1,608 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::{
+    history::{History, Record},
+    ir::Op,
+    limits::Meter,
+    BrainfuckProgram, ExecError, Instruction, Limits, MachineConfig, Tape,
+};
+
+/// Reference interpreter walking the [`Instruction`] tree
+///
+/// This is the semantic ground truth for every other way of running a
+/// program in this crate. The tape lives as long as the interpreter, so
+/// running several programs one after the other continues on the same tape.
+///
+/// With [`Limits`] a program that stopped short of a limit can be resumed
+/// after raising it, see [`Interpreter::resume`]. With a history limit it
+/// keeps a [`history`](crate::history) and can step backwards.
+pub struct Interpreter<R, W> {
+    tape: Tape,
+    input: R,
+    output: W,
+    meter: Meter,
+    /// Where the last run stopped with an error or stepped back to, as the
+    /// index of the next instruction in each loop body from the top level
+    /// down
+    paused: Option<Vec<usize>>,
+    /// Undo records of the latest instructions, by their path like `paused`
+    history: History<Vec<usize>>,
+}
+
+impl<R: Read, W: Write> Interpreter<R, W> {
+    /// Interpreter on a fresh default tape, reading from `input` and writing to `output`
+    pub fn new(input: R, output: W) -> Self {
+        Interpreter::with_config(&MachineConfig::default(), input, output)
+    }
+
+    /// Interpreter on a fresh tape for the given machine model
+    pub fn with_config(config: &MachineConfig, input: R, output: W) -> Self {
+        Interpreter::with_tape(Tape::new(config), input, output)
+    }
+
+    /// Interpreter continuing on an existing tape, following its machine model
+    pub fn with_tape(tape: Tape, input: R, output: W) -> Self {
+        Interpreter {
+            tape,
+            input,
+            output,
+            meter: Meter::default(),
+            paused: None,
+            history: History::new(0),
+        }
+    }
+
+    /// The tape in its current state
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    /// The input still to be read
+    pub fn input(&self) -> &R {
+        &self.input
+    }
+
+    /// Everything written so far
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Give back the tape, input and output
+    pub fn into_parts(self) -> (Tape, R, W) {
+        (self.tape, self.input, self.output)
+    }
+
+    /// Limits for running instructions, the fuel being what is left of it
+    pub fn limits(&self) -> &Limits {
+        &self.meter.limits
+    }
+
+    /// Limit the instructions run by [`Interpreter::run`],
+    /// [`Interpreter::run_instructions`], [`Interpreter::resume`] and
+    /// [`Interpreter::run_ops`], and the growth of the tape
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.tape.set_max_len(limits.max_tape_len);
+        self.meter = Meter::new(limits);
+    }
+
+    /// Give more fuel, e.g. to resume a program that ran out
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.meter.limits.add_fuel(fuel);
+    }
+
+    /// Keep undo records of at most `limit` instructions run by
+    /// [`Interpreter::run`], [`Interpreter::run_instructions`] and
+    /// [`Interpreter::resume`], 0 turning the history off as it is at first
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    /// Undo records of the latest instructions, the newest last, each at
+    /// the index of the instruction in each loop body from the top level down
+    pub fn history(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &Record<Vec<usize>>> + ExactSizeIterator {
+        self.history.records().iter()
+    }
+
+    /// Undo the latest recorded instruction, giving back whether there was
+    /// one
+    ///
+    /// [`Interpreter::resume`] goes on from the undone instruction, reading
+    /// the same input again and not writing output a second time.
+    pub fn step_back(&mut self) -> Result<bool, ExecError> {
+        let Some(record) = self.history.undo(&mut self.tape)? else {
+            return Ok(false);
+        };
+        self.paused = Some(record.at);
+        Ok(true)
+    }
+
+    /// The history with what undoing gave back, see
+    /// [`Interpreter::history`] for the records alone
+    pub(crate) fn recorded(&self) -> &History<Vec<usize>> {
+        &self.history
+    }
+
+    /// Path like in [`Interpreter::history`] to the latest recorded
+    /// instruction writing the cell at `position`
+    pub fn last_write(&self, position: isize) -> Option<&[usize]> {
+        let (_, record) = self.history.last_write(position)?;
+        Some(&record.at)
+    }
+
+    /// Run a whole program and flush the output afterwards
+    pub fn run(&mut self, program: &BrainfuckProgram) -> Result<(), ExecError> {
+        self.run_instructions(program)
+    }
+
+    /// Run a sequence of instructions and flush the output afterwards
+    pub fn run_instructions(&mut self, instructions: &[Instruction]) -> Result<(), ExecError> {
+        self.paused = None;
+        self.history.clear();
+        self.resume(instructions)
+    }
+
+    /// Continue the instructions the last run stopped in with an error,
+    /// at the instruction that failed, and flush the output afterwards
+    ///
+    /// This is how a program goes on after [`ExecError::OutOfFuel`] and
+    /// more fuel, or after raising any other limit, and after
+    /// [`Interpreter::step_back`]. The instructions must be the same as in
+    /// the last run; if that finished, they run from the start.
+    pub fn resume(&mut self, instructions: &[Instruction]) -> Result<(), ExecError> {
+        let mut frames = self.frames(instructions);
+        self.paused = None;
+        let result = self.execute(&mut frames);
+        if result.is_err() {
+            self.paused = Some(path(&frames));
+        }
+        self.output.flush()?;
+        result
+    }
+
+    /// Run only the next instruction and stay paused behind it, giving back
+    /// whether there was one
+    ///
+    /// The end of a loop body goes with the test of the loop it goes back
+    /// to, as the step for the `]`. Unlike [`Interpreter::resume`] this stays
+    /// at the end once the instructions are done, and does not flush.
+    pub(crate) fn step_once(&mut self, instructions: &[Instruction]) -> Result<bool, ExecError> {
+        let mut frames = self.frames(instructions);
+        let result = self.execute_next(&mut frames);
+        self.paused = Some(path(&frames));
+        result
+    }
+
+    /// Flush the output, which [`Interpreter::step_once`] leaves to the
+    /// caller
+    pub(crate) fn flush(&mut self) -> Result<(), ExecError> {
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Where [`Interpreter::step_once`] stopped, as in
+    /// [`Interpreter::history`]
+    pub(crate) fn paused(&self) -> &[usize] {
+        self.paused.as_deref().unwrap_or_default()
+    }
+
+    /// Loop bodies to run to get back to where the last run stopped, each
+    /// with the index of its next instruction
+    fn frames<'p>(&self, instructions: &'p [Instruction]) -> Vec<(&'p [Instruction], usize)> {
+        let path = self.paused();
+        let mut frames = vec![(instructions, path.first().copied().unwrap_or(0))];
+        for &index in path.iter().skip(1) {
+            let (body, at) = frames[frames.len() - 1];
+            let Some(Instruction::Loop(inner)) = body.get(at) else {
+                break;
+            };
+            frames.push((inner, index));
+        }
+        frames
+    }
+
+    /// Run until the outermost body is done, leaving `frames` at the
+    /// instruction that failed on an error
+    fn execute(&mut self, frames: &mut Vec<(&[Instruction], usize)>) -> Result<(), ExecError> {
+        while self.execute_next(frames)? {}
+        Ok(())
+    }
+
+    /// Run the next instruction, giving back false once the outermost body
+    /// is done
+    fn execute_next(
+        &mut self,
+        frames: &mut Vec<(&[Instruction], usize)>,
+    ) -> Result<bool, ExecError> {
+        // The end of a loop body goes back to the test of the loop
+        let mut end = None;
+        let instruction = loop {
+            let (body, index) = frames[frames.len() - 1];
+            if let Some(instruction) = body.get(index) {
+                break instruction;
+            }
+            if frames.len() == 1 {
+                return Ok(false);
+            }
+            frames.pop();
+            end = Some(index);
+        };
+        self.meter.charge()?;
+        // An instruction that failed is paid for when it runs again
+        let mut record = Record::new(Vec::new());
+        let enter = self
+            .step(instruction, &mut record)
+            .inspect_err(|_| self.meter.refund())?;
+        if self.history.is_recording() {
+            record.at = path(frames);
+            record.at.extend(end);
+            self.history.push(record);
+        }
+        match enter {
+            Some(inner) => frames.push((inner, 0)),
+            None => {
+                let last = frames.len() - 1;
+                frames[last].1 += 1;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Run a single instruction, noting in `record` how to undo it and
+    /// giving back the loop body to enter
+    fn step<'p>(
+        &mut self,
+        instruction: &'p Instruction,
+        record: &mut Record<Vec<usize>>,
+    ) -> Result<Option<&'p [Instruction]>, ExecError> {
+        let (pointer, value) = (self.tape.pointer(), self.tape.get());
+        let mut enter = None;
+        match instruction {
+            Instruction::RightShift => {
+                self.tape.move_by(1)?;
+                record.moved_from = Some(pointer);
+            }
+            Instruction::LeftShift => {
+                self.tape.move_by(-1)?;
+                record.moved_from = Some(pointer);
+            }
+            Instruction::Increment => {
+                self.tape.add(1)?;
+                record.write = Some((pointer, value));
+            }
+            Instruction::Decrement => {
+                self.tape.add(-1)?;
+                record.write = Some((pointer, value));
+            }
+            Instruction::Output => {
+                self.write_cell(0)?;
+                record.output = true;
+            }
+            Instruction::Input => {
+                let byte = self.history.read(&mut self.input)?;
+                if self.tape.store_input(0, byte)? {
+                    record.write = Some((pointer, value));
+                }
+                record.input = byte;
+            }
+            Instruction::Loop(inner) => {
+                if value != 0 {
+                    enter = Some(&inner[..]);
+                }
+            }
+        }
+        Ok(enter)
+    }
+
+    /// Run lowered [`Op`]s and flush the output afterwards
+    ///
+    /// Ops use up fuel and stop at the deadline like instructions do, every
+    /// step of an [`Op::Scan`] and every loop test costing fuel as well, but
+    /// cannot be resumed after running into a limit.
+    pub fn run_ops(&mut self, ops: &[Op]) -> Result<(), ExecError> {
+        let result = self.execute_ops(ops);
+        self.output.flush()?;
+        result
+    }
+
+    fn execute_ops(&mut self, ops: &[Op]) -> Result<(), ExecError> {
+        for op in ops {
+            self.meter.charge()?;
+            match op {
+                Op::Add { offset, delta } => self.tape.add_at(*offset, (*delta).into())?,
+                Op::Move(distance) => self.tape.move_by(*distance)?,
+                Op::SetZero { offset } => self.tape.set_at(*offset, 0)?,
+                Op::AddMul { offset, factor } => self.tape.add_product(*offset, *factor)?,
+                Op::Scan(step) => {
+                    while self.tape.get() != 0 {
+                        self.meter.charge()?;
+                        self.tape.move_by(*step)?;
+                    }
+                }
+                Op::Output { offset } => self.write_cell(*offset)?,
+                Op::Input { offset } => self.read_cell(*offset)?,
+                Op::Loop(body) => {
+                    while self.tape.get() != 0 {
+                        self.execute_ops(body)?;
+                        self.meter.charge()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the cell at `offset`, i.e. its lowest byte
+    fn write_cell(&mut self, offset: isize) -> Result<(), ExecError> {
+        let byte = self.tape.get_at(offset) as u8;
+        self.history.write(&mut self.output, byte)
+    }
+
+    /// Read a byte into the cell at `offset`, following the EOF policy at the end of input
+    fn read_cell(&mut self, offset: isize) -> Result<(), ExecError> {
+        let byte = self.history.read(&mut self.input)?;
+        self.tape.store_input(offset, byte)?;
+        Ok(())
+    }
+}
+
+/// Index of the next instruction in each loop body being run
+fn path(frames: &[(&[Instruction], usize)]) -> Vec<usize> {
+    frames.iter().map(|&(_, index)| index).collect()
+}
+
+/// Read a single byte, `None` meaning end of input
+pub(crate) fn read_byte(input: &mut impl Read) -> Result<Option<u8>, ExecError> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Run a program on a fresh default tape, giving back the tape it left behind
+pub fn run(
+    program: &BrainfuckProgram,
+    input: impl Read,
+    output: impl Write,
+) -> Result<Tape, ExecError> {
+    let mut interpreter = Interpreter::new(input, output);
+    interpreter.run(program)?;
+    Ok(interpreter.into_parts().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CellWidth, EofBehavior, Overflow, TapeLength};
+    use std::time::{Duration, Instant};
+
+    /// Run `source` with the given input and collect the output
+    fn run_source(source: &str, input: &[u8]) -> Result<Vec<u8>, ExecError> {
+        run_source_with(&MachineConfig::default(), source, input)
+    }
+
+    /// Run `source` on the given machine and collect the output
+    fn run_source_with(
+        config: &MachineConfig,
+        source: &str,
+        input: &[u8],
+    ) -> Result<Vec<u8>, ExecError> {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let mut interpreter = Interpreter::with_config(config, input, Vec::new());
+        interpreter.run(&program)?;
+        Ok(interpreter.into_parts().2)
+    }
+
+    #[test]
+    fn test_run_hello_world() {
+        // taken from https://en.wikipedia.org/wiki/Brainfuck#Hello_World!
+        let output = run_source(include_str!("../programs/hello_world.bf"), b"").unwrap();
+        assert_eq!(output, b"Hello World!\n");
+    }
+
+    #[test]
+    fn test_run_rot13() {
+        // taken from https://en.wikipedia.org/wiki/Brainfuck#ROT13
+        let output = run_source(include_str!("../programs/rot13.bf"), b"Hello, World!").unwrap();
+        assert_eq!(output, b"Uryyb, Jbeyq!");
+    }
+
+    #[test]
+    fn test_run_wrapping() {
+        // Cells wrap around in both directions
+        assert_eq!(run_source("-.+.", b"").unwrap(), [255, 0]);
+    }
+
+    #[test]
+    fn test_run_input_eof() {
+        // The cell is left unchanged once the input is used up
+        assert_eq!(run_source(",.+++,.", b"a").unwrap(), b"ad");
+    }
+
+    #[test]
+    fn test_run_eof_policies() {
+        // `,` at the end of input for each EOF policy
+        let source = "+++++,.";
+        for (eof, expected) in [
+            (EofBehavior::Unchanged, 5),
+            (EofBehavior::Zero, 0),
+            (EofBehavior::MinusOne, 255),
+        ] {
+            let config = MachineConfig {
+                eof,
+                ..Default::default()
+            };
+            assert_eq!(run_source_with(&config, source, b"").unwrap(), [expected]);
+        }
+    }
+
+    #[test]
+    fn test_run_wide_cells() {
+        // 256 only wraps to 0 in 8-bit cells
+        let source = "++++++++++++++++[>++++++++++++++++<-]>[[-]>+<]>.";
+        assert_eq!(run_source(source, b"").unwrap(), [0]);
+        let config = MachineConfig {
+            cell_width: CellWidth::U16,
+            ..Default::default()
+        };
+        assert_eq!(run_source_with(&config, source, b"").unwrap(), [1]);
+    }
+
+    #[test]
+    fn test_run_overflow_error() {
+        // Decrementing an unsigned zero is an error when wrapping is off
+        let config = MachineConfig {
+            overflow: Overflow::Error,
+            ..Default::default()
+        };
+        assert!(matches!(
+            run_source_with(&config, "+--", b""),
+            Err(ExecError::CellOverflow { position: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_run_unbounded_tape() {
+        // Moving left of the start is fine on an unbounded tape
+        let config = MachineConfig {
+            tape_length: TapeLength::Unbounded,
+            ..Default::default()
+        };
+        assert_eq!(run_source_with(&config, "<<+++.>>.", b"").unwrap(), [3, 0]);
+    }
+
+    #[test]
+    fn test_run_pointer_out_of_bounds() {
+        // Moving left of the first cell is an error
+        assert!(matches!(
+            run_source("><<", b""),
+            Err(ExecError::PointerOutOfBounds { pointer: -1 })
+        ));
+    }
+
+    #[test]
+    fn test_interpreter_keeps_tape() {
+        // Consecutive runs continue on the same tape
+        let mut interpreter = Interpreter::new(&b""[..], Vec::new());
+        interpreter.run(&"+++>".parse().unwrap()).unwrap();
+        interpreter.run(&"<.".parse().unwrap()).unwrap();
+        assert_eq!(interpreter.tape().pointer(), 0);
+        assert_eq!(interpreter.into_parts().2, [3]);
+    }
+
+    #[test]
+    fn test_interpreter_limits() {
+        // Running out of fuel inside nested loops and resuming gives the same
+        // result as running without limits
+        let program: BrainfuckProgram = "++[>+++[>++<-]<-]>>.".parse().unwrap();
+        let mut interpreter = Interpreter::new(&b""[..], Vec::new());
+        interpreter.set_limits(Limits {
+            fuel: Some(0),
+            ..Default::default()
+        });
+        let mut stops = 0;
+        while let Err(e) = interpreter.resume(&program) {
+            assert!(matches!(e, ExecError::OutOfFuel));
+            interpreter.add_fuel(7);
+            stops += 1;
+        }
+        assert!(stops > 5);
+        assert_eq!(interpreter.into_parts().2, [12]);
+    }
+
+    #[test]
+    fn test_interpreter_resume_fuel() {
+        // An instruction stopped by the tape limit costs no more fuel in the
+        // end than running without the limit
+        let program: BrainfuckProgram = "+[>+++[-]<-]>>>+".parse().unwrap();
+        let config = MachineConfig {
+            tape_length: TapeLength::Growable,
+            ..Default::default()
+        };
+        let fuel = Some(1000);
+        let mut unlimited = Interpreter::with_config(&config, &b""[..], Vec::new());
+        unlimited.set_limits(Limits {
+            fuel,
+            ..Default::default()
+        });
+        unlimited.run(&program).unwrap();
+
+        let mut interpreter = Interpreter::with_config(&config, &b""[..], Vec::new());
+        interpreter.set_limits(Limits {
+            fuel,
+            max_tape_len: Some(2),
+            ..Default::default()
+        });
+        assert!(matches!(
+            interpreter.run(&program),
+            Err(ExecError::TapeLimit { position: 2 })
+        ));
+        interpreter.set_limits(Limits {
+            max_tape_len: None,
+            ..*interpreter.limits()
+        });
+        interpreter.resume(&program).unwrap();
+        assert_eq!(interpreter.limits().fuel, unlimited.limits().fuel);
+        assert_eq!(interpreter.tape(), unlimited.tape());
+    }
+
+    #[test]
+    fn test_ops_use_fuel() {
+        // Lowered ops stop once the fuel is used up as well
+        let program: BrainfuckProgram = "+[]".parse().unwrap();
+        let mut interpreter = Interpreter::new(&b""[..], Vec::new());
+        interpreter.set_limits(Limits {
+            fuel: Some(100),
+            ..Default::default()
+        });
+        assert!(matches!(
+            interpreter.run_ops(&crate::ir::lower(&program)),
+            Err(ExecError::OutOfFuel)
+        ));
+        assert_eq!(interpreter.limits().fuel, Some(0));
+    }
+
+    #[test]
+    fn test_interpreter_timeout() {
+        // A loop that never ends stops at the deadline
+        let mut interpreter = Interpreter::new(&b""[..], Vec::new());
+        interpreter.set_limits(Limits {
+            deadline: Some(Instant::now() + Duration::from_millis(10)),
+            ..Default::default()
+        });
+        assert!(matches!(
+            interpreter.run(&"+[]".parse().unwrap()),
+            Err(ExecError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_interpreter_history() {
+        // Stepping back inside a loop and resuming ends up where running
+        // straight through does, with the same input and output
+        let program: BrainfuckProgram = ",[.>+<-]>.".parse().unwrap();
+        let mut interpreter = Interpreter::new(&b"\x03"[..], Vec::new());
+        interpreter.set_history_limit(100);
+        interpreter.run(&program).unwrap();
+        let tape = interpreter.tape().clone();
+        assert_eq!(interpreter.last_write(1), Some(&[1, 2][..]));
+        assert_eq!(interpreter.last_write(0), Some(&[1, 4][..]));
+        for _ in 0..7 {
+            assert!(interpreter.step_back().unwrap());
+        }
+        assert_eq!(interpreter.tape().cells()[..2], [1, 2]);
+        assert_eq!(interpreter.history().last().unwrap().at, [1, 0]);
+        interpreter.resume(&program).unwrap();
+        assert_eq!(interpreter.tape(), &tape);
+        while interpreter.step_back().unwrap() {}
+        assert_eq!(interpreter.tape(), &Tape::default());
+        interpreter.resume(&program).unwrap();
+        assert_eq!(interpreter.into_parts().2, b"\x03\x02\x01\x03");
+    }
+}
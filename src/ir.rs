@@ -0,0 +1,490 @@
+//! Optimising intermediate representation
+//!
+//! [`Op`]s are lowered from the [`Instruction`] tree by [`lower`], or by
+//! [`lower_for`] for cells that do not wrap. Runs of `+`/`-` and `>`/`<` are
+//! folded into single operations, pointer movement inside straight-line code
+//! is turned into offsets, and common loop idioms become single operations.
+
+use crate::{
+    passes::{OptLevel, Pipeline},
+    Instruction, MachineConfig, Overflow,
+};
+
+/// A single operation of the optimising IR
+///
+/// Offsets are relative to the data pointer at the time the operation runs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Op {
+    /// Add `delta` to the cell at `offset`
+    Add { offset: isize, delta: i32 },
+    /// Move the data pointer
+    Move(isize),
+    /// Set the cell at `offset` to zero, e.g. `[-]`
+    SetZero { offset: isize },
+    /// Add the current cell times `factor` to the cell at `offset`
+    ///
+    /// The current cell itself is left alone, a multiply loop like `[->++<]`
+    /// becomes `AddMul { offset: 1, factor: 2 }` followed by
+    /// `SetZero { offset: 0 }`.
+    AddMul { offset: isize, factor: i32 },
+    /// Move the pointer by the step until it is on a zero cell, e.g. `[>]`
+    Scan(isize),
+    /// Write the cell at `offset`
+    Output { offset: isize },
+    /// Read into the cell at `offset`
+    Input { offset: isize },
+    /// Repeat the body while the current cell is not zero
+    Loop(Vec<Op>),
+}
+
+/// Lower instructions into optimised IR for wrapping cells
+///
+/// This translates every instruction one to one and then runs the
+/// [`OptLevel::O2`] pipeline over it, see [`passes`](crate::passes) for
+/// picking passes individually.
+///
+/// The result behaves exactly like the instructions with
+/// [`Overflow::Wrap`], except that moves that cancel out no longer fail when
+/// they step off a fixed tape. It is not valid for other overflow policies,
+/// which need [`lower_for`].
+pub fn lower(instructions: &[Instruction]) -> Vec<Op> {
+    lower_for(instructions, &MachineConfig::default())
+}
+
+/// Lower instructions into optimised IR for the given machine
+///
+/// Like [`lower`], but runs of `+`/`-` are only folded as far as the
+/// machine's [`Overflow`] policy allows. Loops that would get stuck at or
+/// fail on the end of the cell range, like `[+]` or a multiplication
+/// counting down from a negative cell, are still lowered to a clear or a
+/// multiplication.
+pub fn lower_for(instructions: &[Instruction], config: &MachineConfig) -> Vec<Op> {
+    Pipeline::for_machine(OptLevel::O2, config)
+        .run(translate(instructions))
+        .0
+}
+
+/// Translate instructions one to one without optimising anything
+pub fn translate(instructions: &[Instruction]) -> Vec<Op> {
+    instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::RightShift => Op::Move(1),
+            Instruction::LeftShift => Op::Move(-1),
+            Instruction::Increment => Op::Add {
+                offset: 0,
+                delta: 1,
+            },
+            Instruction::Decrement => Op::Add {
+                offset: 0,
+                delta: -1,
+            },
+            Instruction::Output => Op::Output { offset: 0 },
+            Instruction::Input => Op::Input { offset: 0 },
+            Instruction::Loop(body) => Op::Loop(translate(body)),
+        })
+        .collect()
+}
+
+/// Merge adjacent additions to the same cell and adjacent moves
+///
+/// Operations that cancel out, like `+-` or `><`, are dropped entirely.
+/// Unless cells wrap, only additions in the same direction are merged, as
+/// `+-` on the largest value saturates or fails instead of doing nothing.
+pub fn fold_runs(ops: Vec<Op>, overflow: Overflow) -> Vec<Op> {
+    let mut folded: Vec<Op> = Vec::with_capacity(ops.len());
+    for op in ops {
+        match (folded.last_mut(), op) {
+            (
+                Some(Op::Add { offset, delta }),
+                Op::Add {
+                    offset: next_offset,
+                    delta: next_delta,
+                },
+            ) if *offset == next_offset
+                && (overflow == Overflow::Wrap || (*delta > 0) == (next_delta > 0)) =>
+            {
+                *delta = delta.wrapping_add(next_delta);
+                if *delta == 0 {
+                    folded.pop();
+                }
+            }
+            (Some(Op::Move(distance)), Op::Move(next)) => {
+                *distance += next;
+                if *distance == 0 {
+                    folded.pop();
+                }
+            }
+            (_, Op::Loop(body)) => folded.push(Op::Loop(fold_runs(body, overflow))),
+            (_, op) => folded.push(op),
+        }
+    }
+    folded
+}
+
+/// Turn moves in straight-line code into offsets on the operations
+///
+/// The pointer is only really moved before loops, scans and multiplications,
+/// which all depend on the current cell, and at the end of each body.
+pub fn fold_offsets(ops: Vec<Op>) -> Vec<Op> {
+    let mut folded = Vec::with_capacity(ops.len());
+    let mut pending = 0;
+    let flush = |folded: &mut Vec<Op>, pending: &mut isize| {
+        if *pending != 0 {
+            folded.push(Op::Move(*pending));
+            *pending = 0;
+        }
+    };
+    for op in ops {
+        match op {
+            Op::Move(distance) => pending += distance,
+            Op::Add { offset, delta } => folded.push(Op::Add {
+                offset: offset + pending,
+                delta,
+            }),
+            Op::SetZero { offset } => folded.push(Op::SetZero {
+                offset: offset + pending,
+            }),
+            Op::Output { offset } => folded.push(Op::Output {
+                offset: offset + pending,
+            }),
+            Op::Input { offset } => folded.push(Op::Input {
+                offset: offset + pending,
+            }),
+            Op::Loop(body) => {
+                flush(&mut folded, &mut pending);
+                folded.push(Op::Loop(fold_offsets(body)));
+            }
+            op @ (Op::AddMul { .. } | Op::Scan(_)) => {
+                flush(&mut folded, &mut pending);
+                folded.push(op);
+            }
+        }
+    }
+    flush(&mut folded, &mut pending);
+    folded
+}
+
+/// Replace `[-]` and `[+]` with [`Op::SetZero`]
+pub fn clear_loops(ops: Vec<Op>) -> Vec<Op> {
+    replace_loops(ops, &|body| match body {
+        // Both directions end on zero once wrapped around
+        [Op::Add {
+            offset: 0,
+            delta: 1 | -1,
+        }] => Some(vec![Op::SetZero { offset: 0 }]),
+        _ => None,
+    })
+}
+
+/// Replace `[>]` style loops with [`Op::Scan`]
+///
+/// Runs need to be folded with [`fold_runs`] first to catch `[>>]`.
+pub fn scan_loops(ops: Vec<Op>) -> Vec<Op> {
+    replace_loops(ops, &|body| match body {
+        [Op::Move(step)] => Some(vec![Op::Scan(*step)]),
+        _ => None,
+    })
+}
+
+/// Replace copy and multiply loops with [`Op::AddMul`]s
+///
+/// Loop bodies are expected to be folded with [`fold_offsets`] already, so
+/// that `[->+<]` shows up as two additions without any moves.
+pub fn multiply_loops(ops: Vec<Op>) -> Vec<Op> {
+    replace_loops(ops, &multiply_loop)
+}
+
+/// Remove loops that can never be entered
+///
+/// The current cell is known to be zero at the start of the program, right
+/// after a loop or scan ends and after it was cleared. Loops, scans,
+/// multiplications and clears at such points do nothing.
+pub fn remove_dead_code(ops: Vec<Op>) -> Vec<Op> {
+    remove_dead_ops(ops, true)
+}
+
+fn remove_dead_ops(ops: Vec<Op>, mut zero: bool) -> Vec<Op> {
+    let mut live = Vec::with_capacity(ops.len());
+    for op in ops {
+        match op {
+            Op::Loop(_) | Op::Scan(_) | Op::AddMul { .. } | Op::SetZero { offset: 0 } if zero => {}
+            Op::Loop(body) => {
+                live.push(Op::Loop(remove_dead_ops(body, false)));
+                zero = true;
+            }
+            op @ (Op::Scan(_) | Op::SetZero { offset: 0 }) => {
+                live.push(op);
+                zero = true;
+            }
+            op @ (Op::Add { offset: 0, .. } | Op::Input { offset: 0 } | Op::Move(_)) => {
+                live.push(op);
+                zero = false;
+            }
+            op => live.push(op),
+        }
+    }
+    live
+}
+
+/// Replace every loop for which `replace` gives operations, innermost first
+fn replace_loops(ops: Vec<Op>, replace: &dyn Fn(&[Op]) -> Option<Vec<Op>>) -> Vec<Op> {
+    let mut replaced = Vec::with_capacity(ops.len());
+    for op in ops {
+        match op {
+            Op::Loop(body) => {
+                let body = replace_loops(body, replace);
+                match replace(&body) {
+                    Some(ops) => replaced.extend(ops),
+                    None => replaced.push(Op::Loop(body)),
+                }
+            }
+            op => replaced.push(op),
+        }
+    }
+    replaced
+}
+
+/// Turn `[->+>++<<]` style loops into multiplications
+///
+/// The body may only add to cells, each other cell at most once so that no
+/// overflow policy sees the additions in a different order, must decrement
+/// the current cell by exactly one and must not move the pointer in the end.
+fn multiply_loop(body: &[Op]) -> Option<Vec<Op>> {
+    let mut counter_seen = false;
+    let mut ops = Vec::with_capacity(body.len());
+    for op in body {
+        match op {
+            Op::Add {
+                offset: 0,
+                delta: -1,
+            } if !counter_seen => counter_seen = true,
+            Op::Add { offset, delta } if *offset != 0 => {
+                let twice = ops
+                    .iter()
+                    .any(|op| matches!(op, Op::AddMul { offset: seen, .. } if seen == offset));
+                if twice {
+                    return None;
+                }
+                ops.push(Op::AddMul {
+                    offset: *offset,
+                    factor: *delta,
+                });
+            }
+            _ => return None,
+        }
+    }
+    if !counter_seen {
+        return None;
+    }
+    ops.push(Op::SetZero { offset: 0 });
+    Some(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Interpreter, MachineConfig, TapeLength};
+
+    fn lower_source(source: &str) -> Vec<Op> {
+        lower(&parse(source).unwrap())
+    }
+
+    /// Lower `source` after a `,`, so that leading loops are not dead code
+    fn lower_live(source: &str) -> Vec<Op> {
+        let mut ops = lower_source(&format!(",{source}"));
+        assert_eq!(ops.remove(0), Op::Input { offset: 0 });
+        ops
+    }
+
+    #[test]
+    fn test_lower_runs() {
+        // Runs are folded and cancelling runs vanish
+        assert_eq!(
+            lower_source("+++--<<>"),
+            vec![
+                Op::Add {
+                    offset: 0,
+                    delta: 1
+                },
+                Op::Move(-1)
+            ]
+        );
+        assert_eq!(lower_source("+-<>"), vec![]);
+    }
+
+    #[test]
+    fn test_lower_runs_without_wrapping() {
+        // Additions in opposite directions stay apart unless cells wrap, and
+        // a loop adding to a cell twice is no multiplication
+        let config = MachineConfig {
+            overflow: Overflow::Saturate,
+            ..Default::default()
+        };
+        let add = |delta| Op::Add { offset: 0, delta };
+        assert_eq!(
+            lower_for(&parse("++-+-").unwrap(), &config),
+            [add(2), add(-1), add(1), add(-1)]
+        );
+        assert_eq!(lower_for(&parse("+-").unwrap(), &config), [add(1), add(-1)]);
+        assert_eq!(
+            lower_live("[->++>+<-<]")[0],
+            Op::Loop(vec![
+                add(-1),
+                Op::Add {
+                    offset: 1,
+                    delta: 2
+                },
+                Op::Add {
+                    offset: 2,
+                    delta: 1
+                },
+                Op::Add {
+                    offset: 1,
+                    delta: -1
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lower_offsets() {
+        // Moves between additions become offsets
+        assert_eq!(
+            lower_source(">+>>-.<"),
+            vec![
+                Op::Add {
+                    offset: 1,
+                    delta: 1
+                },
+                Op::Add {
+                    offset: 3,
+                    delta: -1
+                },
+                Op::Output { offset: 3 },
+                Op::Move(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lower_clear_loop() {
+        // Both directions of the clear idiom
+        assert_eq!(lower_live("[-]"), vec![Op::SetZero { offset: 0 }]);
+        assert_eq!(lower_live("[+]"), vec![Op::SetZero { offset: 0 }]);
+    }
+
+    #[test]
+    fn test_lower_copy_and_multiply_loops() {
+        // A copy to two cells and a multiplication by three
+        assert_eq!(
+            lower_live("[->+>+<<]"),
+            vec![
+                Op::AddMul {
+                    offset: 1,
+                    factor: 1
+                },
+                Op::AddMul {
+                    offset: 2,
+                    factor: 1
+                },
+                Op::SetZero { offset: 0 },
+            ]
+        );
+        assert_eq!(
+            lower_live("[<---->-]"),
+            vec![
+                Op::AddMul {
+                    offset: -1,
+                    factor: -4
+                },
+                Op::SetZero { offset: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lower_scan_loop() {
+        // Scans in both directions
+        assert_eq!(lower_live("[>]"), vec![Op::Scan(1)]);
+        assert_eq!(lower_live("[<<<]"), vec![Op::Scan(-3)]);
+    }
+
+    #[test]
+    fn test_lower_keeps_other_loops() {
+        // Loops that move or do I/O are kept, with their bodies optimised
+        assert_eq!(
+            lower_live("[->+<<]"),
+            vec![Op::Loop(vec![
+                Op::Add {
+                    offset: 0,
+                    delta: -1
+                },
+                Op::Add {
+                    offset: 1,
+                    delta: 1
+                },
+                Op::Move(-1),
+            ])]
+        );
+        assert_eq!(
+            lower_live("[-.]"),
+            vec![Op::Loop(vec![
+                Op::Add {
+                    offset: 0,
+                    delta: -1
+                },
+                Op::Output { offset: 0 },
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_remove_dead_code() {
+        // Loops at the start and after clears or other loops never run
+        assert_eq!(lower_source("[.][-]+[>][-][-][<]"), lower_source("+[>]"));
+        // Anything touching the current cell makes the next loop live again
+        assert_eq!(
+            remove_dead_code(translate(&parse("[-],[-]>[-]").unwrap())),
+            translate(&parse(",[-]>[-]").unwrap())
+        );
+        // Clearing another cell keeps the current one zero
+        assert_eq!(
+            lower_source(",[-]>[-]<[.]"),
+            vec![
+                Op::Input { offset: 0 },
+                Op::SetZero { offset: 0 },
+                Op::SetZero { offset: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lower_matches_interpreter() {
+        // Lowered programs behave exactly like the reference interpreter
+        let programs = [
+            (include_str!("../programs/hello_world.bf"), &b""[..]),
+            (include_str!("../programs/rot13.bf"), b"Hello, World!"),
+            (
+                "++++++++[>++++[>++>+++<<-]>-[<+>>+<-]<<-]>>[->>+<<]>>.<.>>[<]",
+                b"",
+            ),
+            (">>+++[<,.>>[-]<[>+<-]>[<+>-]<-]<[>]", b"abc"),
+        ];
+        let config = MachineConfig {
+            tape_length: TapeLength::Unbounded,
+            ..Default::default()
+        };
+        for (source, input) in programs {
+            let instructions = parse(source).unwrap();
+            let mut reference = Interpreter::with_config(&config, input, Vec::new());
+            reference.run_instructions(&instructions).unwrap();
+            let mut lowered = Interpreter::with_config(&config, input, Vec::new());
+            lowered.run_ops(&lower(&instructions)).unwrap();
+            let (reference_tape, _, reference_output) = reference.into_parts();
+            let (lowered_tape, _, lowered_output) = lowered.into_parts();
+            assert_eq!(lowered_output, reference_output);
+            assert_eq!(lowered_tape, reference_tape);
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Native x86-64 JIT compiler for Linux
+//!
+//! Programs are lowered with [`ir::lower`], compiled to machine code in an
+//! `mmap`'d buffer and called directly. The code comes from the same encoder
+//! as in the ELF backend. Input and output go through callbacks into Rust,
+//! so any [`Read`] and [`Write`] can be used.
+//!
+//! The generated code works on 8-bit wrapping cells on a fixed tape. Every
+//! pointer move and every offset access is checked against the ends of the
+//! tape, so a program running off the tape stops with
+//! [`ExecError::PointerOutOfBounds`] instead of touching other memory.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    ptr,
+};
+
+use crate::{
+    codegen::x86::{Encoder, Io, OffsetTooLarge},
+    interpreter::read_byte,
+    ir, BrainfuckProgram, CellWidth, ExecError, MachineConfig, Overflow, Tape, TapeLength,
+};
+
+/// Why a program could not be compiled
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JitError {
+    /// The machine model is not supported by the generated code
+    Unsupported(&'static str),
+    /// An offset or move does not fit into a 32-bit displacement
+    OffsetTooLarge(isize),
+    /// Mapping executable memory failed
+    Mmap(i32),
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JitError::Unsupported(what) => write!(f, "the JIT does not support {what}"),
+            JitError::OffsetTooLarge(offset) => write!(f, "offset {offset} is too large"),
+            JitError::Mmap(errno) => write!(f, "mapping executable memory failed: errno {errno}"),
+        }
+    }
+}
+
+impl std::error::Error for JitError {}
+
+/// Status codes returned by the generated code
+const STATUS_OK: u32 = 0;
+const STATUS_OUT_OF_BOUNDS: u32 = 1;
+const STATUS_IO: u32 = 2;
+
+/// Value returned by the input callback to leave the cell unchanged
+const INPUT_UNCHANGED: i64 = -1;
+/// Value returned by the input callback when reading failed
+const INPUT_ERROR: i64 = -2;
+
+/// State shared between the generated code and the I/O callbacks
+///
+/// The generated code only touches the first two fields, at fixed offsets.
+#[repr(C)]
+struct Context<'a> {
+    /// Address that was out of bounds
+    fault: usize,
+    /// Address of the current cell when the program stopped
+    pointer: usize,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    eof_value: Option<u8>,
+    error: Option<io::Error>,
+}
+
+const FAULT_OFFSET: u8 = 0;
+const POINTER_OFFSET: u8 = 8;
+
+extern "sysv64" fn output_callback(context: *mut Context, byte: u32) -> u32 {
+    // SAFETY: the generated code passes along the context it was called with
+    let context = unsafe { &mut *context };
+    match context.output.write_all(&[byte as u8]) {
+        Ok(()) => 0,
+        Err(e) => {
+            context.error = Some(e);
+            1
+        }
+    }
+}
+
+extern "sysv64" fn input_callback(context: *mut Context) -> i64 {
+    // SAFETY: the generated code passes along the context it was called with
+    let context = unsafe { &mut *context };
+    match read_byte(&mut context.input) {
+        Ok(Some(byte)) => byte.into(),
+        Ok(None) => context.eof_value.map_or(INPUT_UNCHANGED, i64::from),
+        Err(ExecError::Io(e)) => {
+            context.error = Some(e);
+            INPUT_ERROR
+        }
+        Err(_) => unreachable!("reading only fails with i/o errors"),
+    }
+}
+
+/// Signature of the generated code
+type EntryPoint = unsafe extern "sysv64" fn(*mut u8, usize, *mut Context) -> u32;
+
+/// A program compiled to native code
+pub struct JitProgram {
+    memory: *mut libc::c_void,
+    size: usize,
+    config: MachineConfig,
+    tape_len: usize,
+}
+
+impl JitProgram {
+    /// Compile a program for the given machine model
+    pub fn compile(program: &BrainfuckProgram, config: &MachineConfig) -> Result<Self, JitError> {
+        if config.cell_width != CellWidth::U8 {
+            return Err(JitError::Unsupported("cells wider than 8 bits"));
+        }
+        if config.overflow != Overflow::Wrap {
+            return Err(JitError::Unsupported("cells that do not wrap"));
+        }
+        let TapeLength::Fixed(tape_len) = config.tape_length else {
+            return Err(JitError::Unsupported("growing tapes"));
+        };
+        let mut encoder = Encoder::default();
+        prologue(&mut encoder);
+        encoder
+            .body(&ir::lower(program), &Callbacks)
+            .map_err(|OffsetTooLarge(offset)| JitError::OffsetTooLarge(offset))?;
+        let code = epilogue(encoder);
+        // SAFETY: a fresh anonymous mapping, only written to before it is made executable
+        unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                code.len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return Err(JitError::Mmap(
+                    io::Error::last_os_error().raw_os_error().unwrap_or(0),
+                ));
+            }
+            ptr::copy_nonoverlapping(code.as_ptr(), memory.cast(), code.len());
+            if libc::mprotect(memory, code.len(), libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                let errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+                libc::munmap(memory, code.len());
+                return Err(JitError::Mmap(errno));
+            }
+            Ok(JitProgram {
+                memory,
+                size: code.len(),
+                config: *config,
+                tape_len,
+            })
+        }
+    }
+
+    /// Run the program on a fresh tape, giving back the tape it left behind
+    pub fn run(&self, mut input: impl Read, mut output: impl Write) -> Result<Tape, ExecError> {
+        let mut cells = vec![0u8; self.tape_len];
+        let mut context = Context {
+            fault: 0,
+            pointer: 0,
+            input: &mut input,
+            output: &mut output,
+            eof_value: self.config.eof_value().map(|value| value as u8),
+            error: None,
+        };
+        let start = cells.as_mut_ptr();
+        // SAFETY: the code was generated by `compile` for exactly this
+        // signature and never leaves the tape it is given
+        let status = unsafe {
+            let entry: EntryPoint = std::mem::transmute(self.memory);
+            entry(start, cells.len(), &mut context)
+        };
+        let position = |address: usize| address.wrapping_sub(start as usize) as isize;
+        let pointer = position(context.pointer);
+        let (fault, error) = (context.fault, context.error.take());
+        output.flush()?;
+        match status {
+            STATUS_OK => Ok(Tape::from_cells(
+                &self.config,
+                cells.into_iter().map(i64::from).collect(),
+                pointer,
+            )),
+            STATUS_OUT_OF_BOUNDS => Err(ExecError::PointerOutOfBounds {
+                pointer: position(fault),
+            }),
+            STATUS_IO => Err(error.expect("i/o failures leave their error").into()),
+            status => unreachable!("unknown status {status}"),
+        }
+    }
+}
+
+impl Drop for JitProgram {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created in `compile` and is not used anymore
+        unsafe {
+            libc::munmap(self.memory, self.size);
+        }
+    }
+}
+
+/// Set up the registers from the arguments, saving the ones the generated
+/// code uses
+///
+/// Besides the registers of the [`Encoder`], `r12` holds the [`Context`].
+fn prologue(encoder: &mut Encoder) {
+    // Save callee-saved registers, five pushes also align the stack
+    encoder.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+    encoder.emit(&[0x48, 0x89, 0xfb]); // mov rbx, rdi
+    encoder.emit(&[0x49, 0x89, 0xfd]); // mov r13, rdi
+    encoder.emit(&[0x4c, 0x8d, 0x34, 0x37]); // lea r14, [rdi + rsi]
+    encoder.emit(&[0x49, 0x89, 0xd4]); // mov r12, rdx
+}
+
+/// Input and output through the callbacks
+struct Callbacks;
+
+impl Io for Callbacks {
+    fn output(&self, encoder: &mut Encoder, offset: i32) {
+        encoder.emit(&[0x4c, 0x89, 0xe7]); // mov rdi, r12
+        encoder.emit(&[0x0f, 0xb6, 0xb3]); // movzx esi, byte [rbx + offset]
+        encoder.emit_i32(offset);
+        call(encoder, output_callback as *const () as usize);
+        encoder.emit(&[0x85, 0xc0]); // test eax, eax
+        encoder.io_error_jump(&[0x0f, 0x85]); // jnz io_error
+    }
+
+    fn input(&self, encoder: &mut Encoder, offset: i32) {
+        encoder.emit(&[0x4c, 0x89, 0xe7]); // mov rdi, r12
+        call(encoder, input_callback as *const () as usize);
+        encoder.emit(&[0x48, 0x83, 0xf8, INPUT_UNCHANGED as u8]); // cmp rax, -1
+        let unchanged = encoder.jump(&[0x0f, 0x84]); // je unchanged
+        encoder.emit(&[0x48, 0x83, 0xf8, INPUT_ERROR as u8]); // cmp rax, -2
+        encoder.io_error_jump(&[0x0f, 0x84]); // je io_error
+                                              // mov byte [rbx + offset], al
+        encoder.emit(&[0x88, 0x83]);
+        encoder.emit_i32(offset);
+        let end = encoder.here();
+        encoder.patch(unchanged, end);
+    }
+}
+
+/// Call a function at a fixed address
+fn call(encoder: &mut Encoder, address: usize) {
+    encoder.emit(&[0x48, 0xb8]); // mov rax, address
+    encoder.emit(&(address as u64).to_le_bytes());
+    encoder.emit(&[0xff, 0xd0]); // call rax
+}
+
+/// Emit the exit paths and patch all jumps to them
+fn epilogue(mut encoder: Encoder) -> Vec<u8> {
+    encoder.emit(&[0x31, 0xc0]); // xor eax, eax
+    let ok = encoder.jump(&[0xe9]); // jmp exit
+
+    let fault = encoder.here();
+    // mov [r12 + FAULT_OFFSET], rax
+    encoder.emit(&[0x49, 0x89, 0x44, 0x24, FAULT_OFFSET]);
+    encoder.emit(&[0xb8]); // mov eax, STATUS_OUT_OF_BOUNDS
+    encoder.emit_i32(STATUS_OUT_OF_BOUNDS as i32);
+    let fault_exit = encoder.jump(&[0xe9]); // jmp exit
+
+    let io = encoder.here();
+    encoder.emit(&[0xb8]); // mov eax, STATUS_IO
+    encoder.emit_i32(STATUS_IO as i32);
+
+    let exit = encoder.here();
+    // mov [r12 + POINTER_OFFSET], rbx
+    encoder.emit(&[0x49, 0x89, 0x5c, 0x24, POINTER_OFFSET]);
+    // Restore callee-saved registers and return
+    encoder.emit(&[0x41, 0x5f, 0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5b, 0xc3]);
+
+    encoder.patch(ok, exit);
+    encoder.patch(fault_exit, exit);
+    encoder.patch_exits(fault, io);
+    encoder.code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EofBehavior, Interpreter};
+
+    #[test]
+    fn test_jit_matches_interpreter() {
+        // Same output and tape as the reference interpreter
+        let programs = [
+            (include_str!("../programs/hello_world.bf"), &b""[..]),
+            (include_str!("../programs/rot13.bf"), b"Hello, World!"),
+            (include_str!("../programs/nested_loops.bf"), b""),
+            ("+++[>,[->+>+<<]>>[-<<+>>]<<.<-]>>>>[<]<<[-]", b"xy"),
+        ];
+        for eof in [
+            EofBehavior::Unchanged,
+            EofBehavior::Zero,
+            EofBehavior::MinusOne,
+        ] {
+            let config = MachineConfig {
+                eof,
+                ..Default::default()
+            };
+            for (source, input) in programs {
+                if eof != EofBehavior::Unchanged && source.starts_with("ROT13") {
+                    continue;
+                }
+                let program: BrainfuckProgram = source.parse().unwrap();
+                let mut reference = Interpreter::with_config(&config, input, Vec::new());
+                reference.run(&program).unwrap();
+                let (expected_tape, _, expected) = reference.into_parts();
+                let jit = JitProgram::compile(&program, &config).unwrap();
+                let mut output = Vec::new();
+                let tape = jit.run(input, &mut output).unwrap();
+                assert_eq!(output, expected, "{source}");
+                assert_eq!(tape, expected_tape, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_jit_out_of_bounds() {
+        // Leaving the tape on either side stops the program
+        let config = MachineConfig {
+            tape_length: TapeLength::Fixed(4),
+            ..Default::default()
+        };
+        let left: BrainfuckProgram = "+[<+]".parse().unwrap();
+        let jit = JitProgram::compile(&left, &config).unwrap();
+        assert!(matches!(
+            jit.run(&b""[..], io::sink()),
+            Err(ExecError::PointerOutOfBounds { pointer: -1 })
+        ));
+        let right: BrainfuckProgram = ">>>>+".parse().unwrap();
+        let jit = JitProgram::compile(&right, &config).unwrap();
+        assert!(matches!(
+            jit.run(&b""[..], io::sink()),
+            Err(ExecError::PointerOutOfBounds { pointer: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_jit_io_error() {
+        // Failing writes are reported as i/o errors
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let program: BrainfuckProgram = "+.".parse().unwrap();
+        let jit = JitProgram::compile(&program, &MachineConfig::default()).unwrap();
+        assert!(matches!(jit.run(&b""[..], Broken), Err(ExecError::Io(_))));
+    }
+
+    #[test]
+    fn test_jit_unsupported_config() {
+        // Only wrapping 8-bit cells on fixed tapes are compiled
+        let program = BrainfuckProgram::default();
+        let config = MachineConfig {
+            cell_width: CellWidth::U16,
+            ..Default::default()
+        };
+        assert!(matches!(
+            JitProgram::compile(&program, &config),
+            Err(JitError::Unsupported(_))
+        ));
+    }
+}
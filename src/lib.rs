@@ -1,12 +1,42 @@
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    combinator::{map, value},
+    bytes::complete::{tag, take_till},
+    combinator::value,
     multi::many0,
-    sequence::tuple,
+    sequence::{preceded, tuple},
     IResult,
 };
 
+pub mod bytecode;
+pub mod codegen;
+mod config;
+pub mod debugger;
+mod error;
+pub mod format;
+pub mod generate;
+pub mod history;
+pub mod interpreter;
+pub mod ir;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+mod limits;
+pub mod minify;
+pub mod passes;
+mod program;
+pub mod repl;
+mod span;
+mod tape;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod vm;
+
+pub use config::{CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength};
+pub use error::{ExecError, ParseError};
+pub use interpreter::Interpreter;
+pub use limits::Limits;
+pub use span::{strip_spans, Position, SpannedInstruction};
+pub use tape::{Tape, DEFAULT_TAPE_LEN};
+
 /// All instructions
 #[derive(Debug, PartialEq, Clone)]
 pub enum Instruction {
@@ -20,24 +50,108 @@ pub enum Instruction {
 }
 
 // The main structure of a brainfuck program
+#[derive(Debug, Default, PartialEq, Clone)]
 pub struct BrainfuckProgram(Vec<Instruction>);
 
+/// Deepest nesting of loops the parser accepts
+///
+/// Everything working on the instruction tree recurses into loops; up to
+/// this depth that fits into the 2 MiB a test thread gets, even in debug
+/// builds.
+pub const MAX_NESTING: usize = 256;
+
+/// The characters that make up brainfuck commands
+const COMMANDS: &str = "><+-.,[]";
+
+/// How characters that are not commands are treated
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ParseMode {
+    /// Skip every non-command character, as the language spec says
+    #[default]
+    Comments,
+    /// Reject every non-command character, useful for linting
+    Strict,
+}
+
 /// Parse entire brainfuck code
 /// This is the main point of entry
-pub fn parse(input: &str) -> IResult<&str, Vec<Instruction>> {
+pub fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    parse_with_mode(input, ParseMode::Comments)
+}
+
+/// Parse entire brainfuck code, failing on any non-command character
+pub fn parse_strict(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    parse_with_mode(input, ParseMode::Strict)
+}
+
+/// Parse entire brainfuck code using the given [`ParseMode`]
+pub fn parse_with_mode(input: &str, mode: ParseMode) -> Result<Vec<Instruction>, ParseError> {
+    // The parser recurses into loops as well, so check before parsing
+    if nesting(input) > MAX_NESTING {
+        return Err(ParseError::diagnose(input, mode));
+    }
     // Fail on remaining output, i.e. unexpected tokens
-    match many0(parse_instruction)(input) {
-        Ok(("", instructions)) => Ok(("", instructions)),
-        Ok((rest, _)) => Err(nom::Err::Failure(nom::error::Error::new(
-            rest,
-            nom::error::ErrorKind::Eof,
-        ))),
-        Err(e) => Err(e),
+    match tuple((|i| parse_body(i, mode), |i| skip_comments(i, mode)))(input) {
+        Ok(("", (instructions, _))) => Ok(instructions),
+        _ => Err(ParseError::diagnose(input, mode)),
     }
 }
 
+/// Parse entire brainfuck code, keeping the source position of every instruction
+pub fn parse_spanned(input: &str) -> Result<Vec<SpannedInstruction>, ParseError> {
+    parse_spanned_with_mode(input, ParseMode::Comments)
+}
+
+/// Parse entire brainfuck code with source positions using the given [`ParseMode`]
+pub fn parse_spanned_with_mode(
+    input: &str,
+    mode: ParseMode,
+) -> Result<Vec<SpannedInstruction>, ParseError> {
+    let instructions = parse_with_mode(input, mode)?;
+    Ok(span::attach_spans(input, instructions))
+}
+
+/// How deep the loops of the source are nested, counting only matching
+/// brackets
+fn nesting(input: &str) -> usize {
+    let (mut depth, mut deepest) = (0usize, 0);
+    for c in input.chars() {
+        match c {
+            '[' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+/// Skip comments, i.e. anything up to the next command
+/// In strict mode there are no comments, so nothing gets skipped
+fn skip_comments(input: &str, mode: ParseMode) -> IResult<&str, ()> {
+    match mode {
+        ParseMode::Comments => value((), take_till(|c| COMMANDS.contains(c)))(input),
+        ParseMode::Strict => Ok((input, ())),
+    }
+}
+
+/// Parse a sequence of instructions, each optionally preceded by comments
+fn parse_body(input: &str, mode: ParseMode) -> IResult<&str, Vec<Instruction>> {
+    many0(preceded(
+        |i| skip_comments(i, mode),
+        |i| parse_instruction(i, mode),
+    ))(input)
+}
+
 /// Parse a basic instruction or loop
-fn parse_instruction(input: &str) -> IResult<&str, Instruction> {
+fn parse_instruction(input: &str, mode: ParseMode) -> IResult<&str, Instruction> {
+    // Loops skip the `alt`, so that each level of nesting takes less stack
+    if input.starts_with('[') {
+        let (input, body) = parse_loop(input, mode)?;
+        return Ok((input, Instruction::Loop(body)));
+    }
     alt((
         value(Instruction::RightShift, tag(">")),
         value(Instruction::LeftShift, tag("<")),
@@ -45,14 +159,16 @@ fn parse_instruction(input: &str) -> IResult<&str, Instruction> {
         value(Instruction::Decrement, tag("-")),
         value(Instruction::Output, tag(".")),
         value(Instruction::Input, tag(",")),
-        map(parse_loop, Instruction::Loop),
     ))(input)
 }
 
 /// Parse a loop, i.e. `[ ]`
-fn parse_loop(input: &str) -> IResult<&str, Vec<Instruction>> {
-    let (input, (_, instructions, _)) =
-        tuple((tag("["), many0(parse_instruction), tag("]")))(input)?;
+fn parse_loop(input: &str, mode: ParseMode) -> IResult<&str, Vec<Instruction>> {
+    // One step after the other rather than a `tuple`, which takes more stack
+    let (input, _) = tag("[")(input)?;
+    let (input, instructions) = parse_body(input, mode)?;
+    let (input, _) = skip_comments(input, mode)?;
+    let (input, _) = tag("]")(input)?;
     Ok((input, instructions))
 }
 
@@ -63,18 +179,36 @@ mod tests {
     #[test]
     fn test_parse_instruction() {
         // Check parsing individual instructions
-        assert_eq!(parse_instruction(">"), Ok(("", Instruction::RightShift)));
-        assert_eq!(parse_instruction("<"), Ok(("", Instruction::LeftShift)));
-        assert_eq!(parse_instruction("+"), Ok(("", Instruction::Increment)));
-        assert_eq!(parse_instruction("-"), Ok(("", Instruction::Decrement)));
-        assert_eq!(parse_instruction("."), Ok(("", Instruction::Output)));
-        assert_eq!(parse_instruction(","), Ok(("", Instruction::Input)));
+        assert_eq!(
+            parse_instruction(">", ParseMode::Strict),
+            Ok(("", Instruction::RightShift))
+        );
+        assert_eq!(
+            parse_instruction("<", ParseMode::Strict),
+            Ok(("", Instruction::LeftShift))
+        );
+        assert_eq!(
+            parse_instruction("+", ParseMode::Strict),
+            Ok(("", Instruction::Increment))
+        );
+        assert_eq!(
+            parse_instruction("-", ParseMode::Strict),
+            Ok(("", Instruction::Decrement))
+        );
+        assert_eq!(
+            parse_instruction(".", ParseMode::Strict),
+            Ok(("", Instruction::Output))
+        );
+        assert_eq!(
+            parse_instruction(",", ParseMode::Strict),
+            Ok(("", Instruction::Input))
+        );
     }
 
     #[test]
     fn test_parse_basic_loop_brainfuck() {
         // Check parsing basic single loop
-        parse_loop("[->+<]").unwrap();
+        parse_loop("[->+<]", ParseMode::Strict).unwrap();
     }
 
     #[test]
@@ -99,6 +233,52 @@ mod tests {
     #[should_panic]
     fn test_parse_wrong_instruction() {
         // Check if wrong instruction correctly gets detected as such
-        parse_instruction("s").unwrap();
+        parse_instruction("s", ParseMode::Strict).unwrap();
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        // Non-command characters are skipped, including inside loops
+        assert_eq!(
+            parse("add one: +\n[ loop -> ]\ndone"),
+            Ok(vec![
+                Instruction::Increment,
+                Instruction::Loop(vec![Instruction::Decrement, Instruction::RightShift]),
+            ])
+        );
     }
+
+    #[test]
+    fn test_parse_strict_rejects_comments() {
+        // Strict mode keeps failing on anything that is not a command
+        assert!(parse_strict("+ +").is_err());
+        assert!(parse_strict("[+ ]").is_err());
+        assert_eq!(parse_strict("+[-]"), parse("+[-]"));
+    }
+
+    #[test]
+    fn test_parse_stray_close_bracket() {
+        // Comments never swallow brackets, so mismatches still fail
+        assert!(parse("+ ]").is_err());
+        assert!(parse("[+ comment").is_err());
+    }
+
+    #[test]
+    fn test_parse_commented_brainfuck_hello_world() {
+        // taken from https://en.wikipedia.org/wiki/Brainfuck#Hello_World!
+        let commented = parse(HELLO_WORLD_COMMENTED).unwrap();
+        let plain = parse("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.").unwrap();
+        assert_eq!(commented, plain);
+    }
+
+    #[test]
+    fn test_parse_commented_brainfuck_rot13() {
+        // taken from https://en.wikipedia.org/wiki/Brainfuck#ROT13
+        let commented = parse(ROT13_COMMENTED).unwrap();
+        let plain = parse("-,+[-[>>++++[>++++++++<-]<+<-[>+>+>-[>>>]<[[>+<-]>>+>]<<<<<-]]>>>[-]+>--[-[<->+++[-]]]<[++++++++++++<[>-[>+>>]>[+[<+>-]>+>>]<<<<<-]>>[<+>-]>[-[-<<[-]>>]<<[<<->>-]>>]<<[<<+>>-]]<[-]<.[-]<-,+]").unwrap();
+        assert_eq!(commented, plain);
+    }
+
+    const HELLO_WORLD_COMMENTED: &str = include_str!("../programs/hello_world.bf");
+    const ROT13_COMMENTED: &str = include_str!("../programs/rot13.bf");
 }
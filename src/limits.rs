@@ -0,0 +1,108 @@
+use std::time::Instant;
+
+use crate::ExecError;
+
+/// Instructions run between two looks at the clock
+const CLOCK_INTERVAL: u32 = 1024;
+
+/// Bounds on what running a program may use, for programs that cannot be
+/// trusted to halt
+///
+/// Running stops with [`ExecError::OutOfFuel`], [`ExecError::TapeLimit`] or
+/// [`ExecError::Timeout`] before the instruction that would go past a limit,
+/// so that it can be resumed once the limit is raised.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Limits {
+    /// Instructions left to run, `None` for no limit
+    ///
+    /// Each instruction the engine runs costs one unit, including every test
+    /// of a loop condition, so the same program needs less fuel on an engine
+    /// running optimised code. An instruction failing with an error costs
+    /// nothing, so resuming it does not pay twice.
+    pub fuel: Option<u64>,
+    /// Number of cells a growable or unbounded tape may grow to
+    pub max_tape_len: Option<usize>,
+    /// Point in time after which running stops
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    /// Give more fuel, nothing happening without a fuel limit
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(left) = &mut self.fuel {
+            *left = left.saturating_add(fuel);
+        }
+    }
+}
+
+/// Keeps track of the fuel and the deadline while running
+#[derive(Debug, Default)]
+pub(crate) struct Meter {
+    pub(crate) limits: Limits,
+    /// Instructions until the next look at the clock
+    until_clock: u32,
+}
+
+impl Meter {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Meter {
+            limits,
+            until_clock: 0,
+        }
+    }
+
+    /// Pay for one instruction, failing if a limit does not allow it
+    #[inline]
+    pub(crate) fn charge(&mut self) -> Result<(), ExecError> {
+        if let Some(fuel) = &mut self.limits.fuel {
+            *fuel = fuel.checked_sub(1).ok_or(ExecError::OutOfFuel)?;
+        }
+        if let Some(deadline) = self.limits.deadline {
+            if self.until_clock == 0 {
+                if Instant::now() >= deadline {
+                    self.refund();
+                    return Err(ExecError::Timeout);
+                }
+                self.until_clock = CLOCK_INTERVAL;
+            }
+            self.until_clock -= 1;
+        }
+        Ok(())
+    }
+
+    /// Give back the fuel of an instruction that did not run or failed, so
+    /// that running it again after an error costs the same as running it
+    /// once
+    #[inline]
+    pub(crate) fn refund(&mut self) {
+        self.limits.add_fuel(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter() {
+        // Fuel runs out and comes back, the deadline is seen right away
+        let mut meter = Meter::new(Limits {
+            fuel: Some(2),
+            ..Default::default()
+        });
+        assert!(meter.charge().is_ok());
+        assert!(meter.charge().is_ok());
+        assert!(matches!(meter.charge(), Err(ExecError::OutOfFuel)));
+        meter.limits.add_fuel(1);
+        assert!(meter.charge().is_ok());
+        assert_eq!(meter.limits.fuel, Some(0));
+
+        let mut meter = Meter::new(Limits {
+            fuel: Some(5),
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        });
+        assert!(matches!(meter.charge(), Err(ExecError::Timeout)));
+        assert_eq!(meter.limits.fuel, Some(5));
+    }
+}
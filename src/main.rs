@@ -1,9 +1,538 @@
-use brainfuck_parser::parse;
-
-fn main() {
-    // Run brainfuck parser
-    let input = "+>>+[->+<]-";
-    let res = parse(input).unwrap();
-    // and print the resulting AST
-    dbg!(res);
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, Read, Write},
+    process::ExitCode,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use brainfuck_parser::{
+    bytecode::Bytecode,
+    codegen,
+    format::{self, FormatOptions},
+    ir,
+    minify::{self, MinifyOptions},
+    parse_spanned, parse_with_mode, passes,
+    repl::{Repl, Response},
+    vm::Vm,
+    BrainfuckProgram, ExecError, Instruction, Interpreter, Limits, MachineConfig, ParseMode,
+};
+
+const USAGE: &str = "usage: brainfuck-parser <command> [<options>] [<file>]
+
+The program is read from <file>, or from stdin if it is missing or `-`.
+
+commands:
+  run        run the program
+  check      check that the program parses
+  fmt        print the program formatted
+  minify     print the program in as few bytes as possible
+  compile    translate the program for another language or platform
+  stats      print sizes of the program
+  ast        print the parsed instructions
+  repl       run lines of code interactively on one tape
+  debug      step through a program in a full-screen debugger, needs a
+             build with `--features tui`
+
+run options:
+  --engine <engine>       interpreter, vm or jit [default: interpreter]
+  --input <file>          read input from a file instead of stdin
+  --fuel <steps>          stop after running this many instructions
+  --max-tape <cells>      stop when the tape would grow past this many cells
+  --timeout <seconds>     stop after this much time
+
+debug options:
+  --input <file>          read input from a file, stdin is the keyboard
+  --markers               add a breakpoint after every `#`
+
+machine options, for run, compile, repl and debug:
+  --cell-width <width>    8, 16, 32 or i32 [default: 8]
+  --overflow <policy>     wrap, error or saturate [default: wrap]
+  --tape <length>         number of cells, growable or unbounded [default: 30000]
+  --eof <behaviour>       unchanged, zero or minus-one [default: unchanged]
+
+check options:
+  --strict                reject everything that is not a command
+
+fmt options:
+  --indent <spaces>       indentation per loop [default: 2]
+  --width <columns>       maximum line width [default: 80]
+  --group <size>          split runs into groups of this size
+  --brackets-on-own-lines put `[` and `]` on lines of their own
+  --check                 only fail if the program is not formatted
+
+minify options:
+  --peephole              also drop commands that have no effect
+  --keep-comments         keep everything that is not a command
+
+compile options:
+  --target <target>       c, rust, llvm, wat, wasm, x86_64-linux-asm or
+                          x86_64-linux-elf
+  -o <output>             write to a file instead of stdout
+
+ast options:
+  --spans                 include source positions";
+
+/// Options setting up the machine
+const MACHINE: [&str; 4] = ["--cell-width", "--overflow", "--tape", "--eof"];
+
+/// Why a command failed
+enum Error {
+    /// The command line is wrong, exits with 2
+    Usage(String),
+    /// Anything else, exits with 1
+    Failed(String),
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(command) = args.first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    let args = &args[1..];
+    let result = match command.as_str() {
+        "run" => run(args),
+        "check" => check(args),
+        "fmt" => fmt(args),
+        "minify" => minify(args),
+        "compile" => compile(args),
+        "stats" => stats(args),
+        "ast" => ast(args),
+        "repl" => repl(args),
+        "debug" => debug(args),
+        "help" | "-h" | "--help" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(Error::Usage(format!("unknown command `{command}`"))),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Error::Usage(message)) => {
+            let usage = USAGE.lines().next().unwrap_or_default();
+            eprintln!("error: {message}\n{usage}\nsee `brainfuck-parser help` for all options");
+            ExitCode::from(2)
+        }
+        Err(Error::Failed(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run a program with one of the engines
+fn run(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(
+        args,
+        &[
+            &MACHINE[..],
+            &["--engine", "--input", "--fuel", "--max-tape", "--timeout"],
+        ]
+        .concat(),
+        &[],
+    )?;
+    let config = args.machine_config()?;
+    let timeout = args
+        .parsed::<f64>("--timeout")?
+        .map(|seconds| {
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|e| Error::Usage(format!("invalid value for --timeout: {e}")))
+        })
+        .transpose()?;
+    let fuel = args.parsed("--fuel")?;
+    let max_tape_len = args.parsed("--max-tape")?;
+    let engine = args.value("--engine").unwrap_or("interpreter");
+    let source = args.source()?;
+    let program = parse(&source)?;
+
+    // A program read from stdin cannot also read its input from there
+    let input: Box<dyn Read> = match args.value("--input") {
+        Some(path) => Box::new(File::open(path).map_err(|e| cannot("read", path, e))?),
+        None if source.from_stdin => Box::new(io::empty()),
+        None => Box::new(io::stdin().lock()),
+    };
+    let output = io::stdout().lock();
+    // Only the program itself runs against the clock
+    let limits = Limits {
+        fuel,
+        max_tape_len,
+        deadline: timeout.map(|timeout| Instant::now() + timeout),
+    };
+    let result = match engine {
+        "interpreter" => {
+            let mut interpreter = Interpreter::with_config(&config, input, output);
+            interpreter.set_limits(limits);
+            interpreter.run(&program)
+        }
+        "vm" => {
+            let mut vm = Vm::with_config(&config, input, output);
+            vm.set_limits(limits);
+            vm.run(&Bytecode::compile(&program, &config))
+        }
+        "jit" if limits != Limits::default() => {
+            return Err(Error::Usage(
+                "the jit engine does not support limits".to_string(),
+            ))
+        }
+        "jit" => run_jit(&program, &config, input, output)?,
+        _ => return Err(Error::Usage(format!("unknown engine `{engine}`"))),
+    };
+    result.map_err(|e| Error::Failed(e.to_string()))
+}
+
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+fn run_jit(
+    program: &BrainfuckProgram,
+    config: &MachineConfig,
+    input: impl Read,
+    output: impl Write,
+) -> Result<Result<(), ExecError>, Error> {
+    let compiled = brainfuck_parser::jit::JitProgram::compile(program, config)
+        .map_err(|e| Error::Failed(e.to_string()))?;
+    Ok(compiled.run(input, output).map(drop))
+}
+
+#[cfg(not(all(feature = "jit", target_arch = "x86_64", target_os = "linux")))]
+fn run_jit(
+    _program: &BrainfuckProgram,
+    _config: &MachineConfig,
+    _input: impl Read,
+    _output: impl Write,
+) -> Result<Result<(), ExecError>, Error> {
+    Err(Error::Failed(
+        "the jit engine needs x86-64 Linux and the `jit` feature".to_string(),
+    ))
+}
+
+/// Only parse a program
+fn check(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[], &["--strict"])?;
+    let mode = if args.switch("--strict") {
+        ParseMode::Strict
+    } else {
+        ParseMode::Comments
+    };
+    let source = args.source()?;
+    parse_with_mode(&source.text, mode).map_err(|e| source.error(e))?;
+    Ok(())
+}
+
+/// Print a program formatted, or check that it already is
+fn fmt(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(
+        args,
+        &["--indent", "--width", "--group"],
+        &["--brackets-on-own-lines", "--check"],
+    )?;
+    let defaults = FormatOptions::default();
+    let options = FormatOptions {
+        indent: args.parsed("--indent")?.unwrap_or(defaults.indent),
+        max_width: args.parsed("--width")?.unwrap_or(defaults.max_width),
+        group: args.parsed("--group")?,
+        brackets_on_own_lines: args.switch("--brackets-on-own-lines"),
+    };
+    let source = args.source()?;
+    let formatted = format::format(&parse(&source)?, &options);
+    if args.switch("--check") {
+        if formatted != source.text {
+            return Err(Error::Failed(format!("{} is not formatted", source.name)));
+        }
+        return Ok(());
+    }
+    write_stdout(formatted.as_bytes())
+}
+
+/// Print a program in as few bytes as possible
+fn minify(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[], &["--peephole", "--keep-comments"])?;
+    let options = MinifyOptions {
+        peephole: args.switch("--peephole"),
+        keep_comments: args.switch("--keep-comments"),
+    };
+    let source = args.source()?;
+    let minified = minify::minify(&source.text, &options).map_err(|e| source.error(e))?;
+    write_stdout(minified.as_bytes())
+}
+
+/// Compile a program for one of the code generation targets
+fn compile(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[&MACHINE[..], &["--target", "-o"]].concat(), &[])?;
+    let config = args.machine_config()?;
+    let target = args
+        .value("--target")
+        .ok_or_else(|| Error::Usage("missing --target".to_string()))?;
+    let program = parse(&args.source()?)?;
+
+    let code = match target {
+        "c" => codegen::c::generate(&program, &config).map(String::into_bytes),
+        "rust" => codegen::rust::generate(&program, &config, codegen::rust::Entry::Main)
+            .map(String::into_bytes),
+        "llvm" => codegen::llvm::generate(&program, &config).map(String::into_bytes),
+        "wat" => codegen::wasm::generate_text(&program, &config).map(String::into_bytes),
+        "wasm" => codegen::wasm::generate_binary(&program, &config),
+        "x86_64-linux-asm" => codegen::asm::generate(&program, &config).map(String::into_bytes),
+        "x86_64-linux-elf" => codegen::elf::generate(&program, &config),
+        _ => return Err(Error::Usage(format!("unknown target `{target}`"))),
+    }
+    .map_err(|e| Error::Failed(e.to_string()))?;
+
+    match args.value("-o") {
+        Some(path) => {
+            fs::write(path, code).map_err(|e| cannot("write", path, e))?;
+            if target == "x86_64-linux-elf" {
+                make_executable(path).map_err(|e| cannot("make executable", path, e))?;
+            }
+            Ok(())
+        }
+        None => write_stdout(&code),
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &str) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Print sizes of a program and of its optimised form
+fn stats(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[], &[])?;
+    let source = args.source()?;
+    let program = parse(&source)?;
+
+    fn loops(instructions: &[Instruction]) -> usize {
+        instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Loop(body) => 1 + loops(body),
+                _ => 0,
+            })
+            .sum()
+    }
+    let rows = [
+        ("source bytes", source.text.len()),
+        ("commands", program.source_len()),
+        ("instructions", program.instruction_count()),
+        ("loops", loops(&program)),
+        ("loop depth", program.depth()),
+        ("ops", passes::count_ops(&ir::translate(&program))),
+        ("optimised ops", passes::count_ops(&ir::lower(&program))),
+    ];
+    let table: String = rows
+        .iter()
+        .map(|(name, value)| format!("{name:<14} {value:>8}\n"))
+        .collect();
+    write_stdout(table.as_bytes())
+}
+
+/// Print the parsed instructions, with or without their positions
+fn ast(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[], &["--spans"])?;
+    let source = args.source()?;
+    let tree = if args.switch("--spans") {
+        let spanned = parse_spanned(&source.text).map_err(|e| source.error(e))?;
+        format!("{spanned:#?}\n")
+    } else {
+        format!("{:#?}\n", parse(&source)?.instructions())
+    };
+    write_stdout(tree.as_bytes())
+}
+
+/// Read lines from stdin and run them on the same tape, until the end of
+/// input or `:quit`
+fn repl(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &MACHINE, &[])?;
+    if args.file.is_some() {
+        return Err(Error::Usage("repl reads from stdin only".to_string()));
+    }
+    let mut repl = Repl::new(&args.machine_config()?);
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout();
+    println!("brainfuck repl, see `:help` for commands");
+    loop {
+        print!("{}", repl.prompt());
+        stdout.flush().map_err(|e| Error::Failed(e.to_string()))?;
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(Error::Failed(format!("cannot read stdin: {e}"))),
+        }
+        // Code reads its input from the lines typed after it
+        match repl.eval(line.trim_end_matches(['\n', '\r']), &mut stdin, &mut stdout) {
+            Ok(Response::Continue) => {}
+            Ok(Response::Message(message)) => println!("{message}"),
+            Ok(Response::Quit) => return Ok(()),
+            Err(e) => eprintln!("error: {e}"),
+        }
+    }
+}
+
+/// Open the full-screen debugger on a program file
+#[cfg(feature = "tui")]
+fn debug(args: &[String]) -> Result<(), Error> {
+    use brainfuck_parser::tui::{self, Session};
+
+    let args = Args::parse(args, &[&MACHINE[..], &["--input"]].concat(), &["--markers"])?;
+    let config = args.machine_config()?;
+    if args.file.as_deref().is_none_or(|file| file == "-") {
+        return Err(Error::Usage(
+            "debug needs a file, stdin is the keyboard".to_string(),
+        ));
+    }
+    let source = args.source()?;
+    let input = match args.value("--input") {
+        Some(path) => fs::read(path).map_err(|e| cannot("read", path, e))?,
+        None => Vec::new(),
+    };
+    let mut session = Session::new(&source.text, &config, io::Cursor::new(input), Vec::new())
+        .map_err(|e| source.error(e))?;
+    if args.switch("--markers") {
+        session.break_on_markers();
+    }
+    tui::run(session).map_err(|e| Error::Failed(format!("terminal error: {e}")))
+}
+
+#[cfg(not(feature = "tui"))]
+fn debug(_args: &[String]) -> Result<(), Error> {
+    Err(Error::Failed(
+        "the debugger needs a build with `--features tui`".to_string(),
+    ))
+}
+
+/// Source code together with where it came from
+struct Source {
+    /// File name, or `<stdin>`
+    name: String,
+    text: String,
+    from_stdin: bool,
+}
+
+impl Source {
+    /// An error about the source, naming it
+    fn error(&self, error: impl Display) -> Error {
+        Error::Failed(format!("{}: {error}", self.name))
+    }
+}
+
+fn parse(source: &Source) -> Result<BrainfuckProgram, Error> {
+    source.text.parse().map_err(|e| source.error(e))
+}
+
+fn write_stdout(bytes: &[u8]) -> Result<(), Error> {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(bytes)
+        .and_then(|()| stdout.flush())
+        .map_err(|e| Error::Failed(format!("cannot write output: {e}")))
+}
+
+fn cannot(action: &str, path: &str, error: io::Error) -> Error {
+    Error::Failed(format!("cannot {action} {path}: {error}"))
+}
+
+/// Options and the file name of a command
+struct Args {
+    values: HashMap<String, String>,
+    switches: HashSet<String>,
+    file: Option<String>,
+}
+
+impl Args {
+    /// Split `args` into known options and at most one file, accepting both
+    /// `--option value` and `--option=value`
+    fn parse(args: &[String], with_value: &[&str], switches: &[&str]) -> Result<Self, Error> {
+        let mut parsed = Args {
+            values: HashMap::new(),
+            switches: HashSet::new(),
+            file: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            if with_value.contains(&name) {
+                let value = inline
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| Error::Usage(format!("missing value for {name}")))?;
+                parsed.values.insert(name.to_string(), value);
+            } else if switches.contains(&name) && inline.is_none() {
+                parsed.switches.insert(name.to_string());
+            } else if arg.starts_with('-') && arg != "-" {
+                return Err(Error::Usage(format!("unexpected option `{arg}`")));
+            } else if parsed.file.is_none() {
+                parsed.file = Some(arg.clone());
+            } else {
+                return Err(Error::Usage(format!("unexpected argument `{arg}`")));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// The value of an option, parsed
+    fn parsed<T>(&self, name: &str) -> Result<Option<T>, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.value(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| Error::Usage(format!("invalid value for {name}: {e}")))
+            })
+            .transpose()
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    /// The machine described by the machine options
+    fn machine_config(&self) -> Result<MachineConfig, Error> {
+        let defaults = MachineConfig::default();
+        Ok(MachineConfig {
+            cell_width: self.parsed("--cell-width")?.unwrap_or(defaults.cell_width),
+            overflow: self.parsed("--overflow")?.unwrap_or(defaults.overflow),
+            tape_length: self.parsed("--tape")?.unwrap_or(defaults.tape_length),
+            eof: self.parsed("--eof")?.unwrap_or(defaults.eof),
+        })
+    }
+
+    /// Read the file, or stdin without one
+    fn source(&self) -> Result<Source, Error> {
+        match self.file.as_deref() {
+            Some(path) if path != "-" => Ok(Source {
+                name: path.to_string(),
+                text: fs::read_to_string(path).map_err(|e| cannot("read", path, e))?,
+                from_stdin: false,
+            }),
+            _ => {
+                let mut text = String::new();
+                io::stdin()
+                    .read_to_string(&mut text)
+                    .map_err(|e| Error::Failed(format!("cannot read stdin: {e}")))?;
+                Ok(Source {
+                    name: "<stdin>".to_string(),
+                    text,
+                    from_stdin: true,
+                })
+            }
+        }
+    }
 }
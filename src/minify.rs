@@ -0,0 +1,182 @@
+//! Shrinking source code to the fewest bytes
+//!
+//! Minifying drops everything but the commands and can also drop commands
+//! that have no effect. Comments may be kept, since they never contain
+//! commands and so cannot change what the program does.
+
+use std::collections::HashSet;
+
+use crate::{parse_spanned, Instruction, ParseError, SpannedInstruction, COMMANDS};
+
+/// What [`minify`] removes besides whitespace and comments
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct MinifyOptions {
+    /// Drop commands that cancel out, like `+-` and `<>`, and loops that can
+    /// never be entered, like the second loop of `[-][-]`
+    pub peephole: bool,
+    /// Keep all non-command characters exactly as they are
+    pub keep_comments: bool,
+}
+
+/// Minify `source`, which has to parse
+///
+/// The peephole cancellations behave exactly like the original for wrapping
+/// cells. With other [`Overflow`](crate::Overflow) policies `-+` no longer
+/// fails on a zero cell, and `<>` no longer fails on the first cell of a
+/// fixed tape.
+pub fn minify(source: &str, options: &MinifyOptions) -> Result<String, ParseError> {
+    let mut instructions = parse_spanned(source)?;
+    if options.peephole {
+        instructions = peephole(instructions, true);
+    }
+    let mut kept = HashSet::new();
+    collect_offsets(&instructions, &mut kept);
+
+    let minified = source
+        .char_indices()
+        .filter(|&(offset, c)| {
+            if COMMANDS.contains(c) {
+                kept.contains(&offset)
+            } else {
+                options.keep_comments
+            }
+        })
+        .map(|(_, c)| c)
+        .collect();
+    Ok(minified)
+}
+
+/// Drop cancelling commands and dead loops from a body
+///
+/// The current cell is zero at the start of the program and right after a
+/// loop, so loops at those points are never entered.
+fn peephole(instructions: Vec<SpannedInstruction>, top_level: bool) -> Vec<SpannedInstruction> {
+    let mut out: Vec<SpannedInstruction> = Vec::new();
+    for instruction in instructions {
+        match instruction {
+            SpannedInstruction::Loop { body, open, close } => {
+                let dead = match out.last() {
+                    None => top_level,
+                    Some(last) => matches!(last, SpannedInstruction::Loop { .. }),
+                };
+                if !dead {
+                    out.push(SpannedInstruction::Loop {
+                        body: peephole(body, false),
+                        open,
+                        close,
+                    });
+                }
+            }
+            SpannedInstruction::Command { instruction, at } => match out.last() {
+                Some(SpannedInstruction::Command {
+                    instruction: last, ..
+                }) if cancels(last, &instruction) => {
+                    out.pop();
+                }
+                _ => out.push(SpannedInstruction::Command { instruction, at }),
+            },
+        }
+    }
+    out
+}
+
+/// Whether running `second` right after `first` does nothing
+fn cancels(first: &Instruction, second: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        (first, second),
+        (Increment, Decrement)
+            | (Decrement, Increment)
+            | (RightShift, LeftShift)
+            | (LeftShift, RightShift)
+    )
+}
+
+/// Byte offsets of all commands in the tree, including both brackets of loops
+fn collect_offsets(instructions: &[SpannedInstruction], offsets: &mut HashSet<usize>) {
+    for instruction in instructions {
+        match instruction {
+            SpannedInstruction::Command { at, .. } => {
+                offsets.insert(at.offset);
+            }
+            SpannedInstruction::Loop { body, open, close } => {
+                offsets.insert(open.offset);
+                collect_offsets(body, offsets);
+                offsets.insert(close.offset);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter, BrainfuckProgram};
+
+    const PEEPHOLE: MinifyOptions = MinifyOptions {
+        peephole: true,
+        keep_comments: false,
+    };
+
+    #[test]
+    fn test_strip_comments() {
+        // Only commands are left, all of them
+        let minified = minify("add: ++ \n[-] +-", &MinifyOptions::default()).unwrap();
+        assert_eq!(minified, "++[-]+-");
+    }
+
+    #[test]
+    fn test_peephole() {
+        // Cancelling commands and dead loops go, even when only cancelling
+        // commands were in between
+        for (source, minified) in [
+            ("+-<>-+><", ""),
+            ("+>+-<-.", "."),
+            ("+[-][-]", "+[-]"),
+            ("+[-]+-[>]", "+[-]"),
+            ("[loop]+", "+"),
+            ("+-[-]+", "+"),
+            ("+[+-]", "+[]"),
+            ("+[[-]]", "+[[-]]"),
+        ] {
+            assert_eq!(minify(source, &PEEPHOLE).unwrap(), minified, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        // Comments stay in place, also around removed commands
+        let options = MinifyOptions {
+            keep_comments: true,
+            ..PEEPHOLE
+        };
+        assert_eq!(
+            minify("+ clear [-] twice [-] +- done", &options).unwrap(),
+            "+ clear [-] twice   done"
+        );
+        assert_eq!(
+            minify(
+                "+[-]\n",
+                &MinifyOptions {
+                    keep_comments: true,
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+            "+[-]\n"
+        );
+    }
+
+    #[test]
+    fn test_same_behaviour() {
+        // Minified programs still do the same thing
+        let source = include_str!("../programs/rot13.bf");
+        for options in [MinifyOptions::default(), PEEPHOLE] {
+            let minified: BrainfuckProgram = minify(source, &options).unwrap().parse().unwrap();
+            let mut output = Vec::new();
+            interpreter::run(&minified, &b"Hello"[..], &mut output).unwrap();
+            assert_eq!(output, b"Uryyb");
+        }
+        assert!(minify("[", &MinifyOptions::default()).is_err());
+    }
+}
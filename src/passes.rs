@@ -0,0 +1,339 @@
+//! Composable optimisation passes over the [`Op`] IR
+//!
+//! Every transformation in [`ir`](crate::ir) is available as a named
+//! [`OptimizationPass`]. A [`Pipeline`] runs passes in order and reports how
+//! much each of them changed, which makes it easy to turn single passes off
+//! or reorder them while hunting for a miscompilation.
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ir::{self, Op},
+    Instruction, MachineConfig, Overflow,
+};
+
+/// A transformation of the IR that keeps the program's behaviour
+pub trait OptimizationPass {
+    /// Short name used to pick the pass and in reports
+    fn name(&self) -> &'static str;
+
+    /// Transform the operations
+    fn run(&self, ops: Vec<Op>) -> Vec<Op>;
+}
+
+/// Merges runs of `+`/`-` and `>`/`<`, see [`ir::fold_runs`]
+#[derive(Default)]
+pub struct FoldRuns {
+    /// Overflow policy the merged runs must behave the same under
+    pub overflow: Overflow,
+}
+
+/// Turns `[-]` into a single clear, see [`ir::clear_loops`]
+pub struct ClearLoops;
+
+/// Turns `[>]` into a single scan, see [`ir::scan_loops`]
+pub struct ScanLoops;
+
+/// Turns moves in straight-line code into offsets, see [`ir::fold_offsets`]
+pub struct FoldOffsets;
+
+/// Turns copy and multiply loops into multiplications, see [`ir::multiply_loops`]
+pub struct MultiplyLoops;
+
+/// Removes loops that can never run, see [`ir::remove_dead_code`]
+pub struct DeadCode;
+
+macro_rules! impl_pass {
+    ($pass:ident, $name:literal, $function:path) => {
+        impl OptimizationPass for $pass {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn run(&self, ops: Vec<Op>) -> Vec<Op> {
+                $function(ops)
+            }
+        }
+    };
+}
+
+impl OptimizationPass for FoldRuns {
+    fn name(&self) -> &'static str {
+        "fold-runs"
+    }
+
+    fn run(&self, ops: Vec<Op>) -> Vec<Op> {
+        ir::fold_runs(ops, self.overflow)
+    }
+}
+
+impl_pass!(ClearLoops, "clear-loops", ir::clear_loops);
+impl_pass!(ScanLoops, "scan-loops", ir::scan_loops);
+impl_pass!(FoldOffsets, "fold-offsets", ir::fold_offsets);
+impl_pass!(MultiplyLoops, "multiply-loops", ir::multiply_loops);
+impl_pass!(DeadCode, "dead-code", ir::remove_dead_code);
+
+/// Names of all built-in passes, in the order [`OptLevel::O2`] runs them
+pub const PASS_NAMES: [&str; 6] = [
+    "fold-runs",
+    "clear-loops",
+    "scan-loops",
+    "fold-offsets",
+    "multiply-loops",
+    "dead-code",
+];
+
+/// Look up a built-in pass by its name, set up for wrapping cells
+pub fn pass_by_name(name: &str) -> Option<Box<dyn OptimizationPass>> {
+    let pass: Box<dyn OptimizationPass> = match name {
+        "fold-runs" => Box::new(FoldRuns::default()),
+        "clear-loops" => Box::new(ClearLoops),
+        "scan-loops" => Box::new(ScanLoops),
+        "fold-offsets" => Box::new(FoldOffsets),
+        "multiply-loops" => Box::new(MultiplyLoops),
+        "dead-code" => Box::new(DeadCode),
+        _ => return None,
+    };
+    Some(pass)
+}
+
+/// Preset pipelines, like a compiler's `-O` flags
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum OptLevel {
+    /// No optimisation, one operation per instruction
+    O0,
+    /// Cheap passes: run folding, clear loops and dead code
+    O1,
+    /// Every built-in pass
+    #[default]
+    O2,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches("-O").trim_start_matches('O') {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            _ => Err(format!("unknown optimisation level `{s}`")),
+        }
+    }
+}
+
+/// An ordered list of passes
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn OptimizationPass>>,
+}
+
+impl Pipeline {
+    /// A pipeline without any passes
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// The pipeline for an optimisation level on wrapping cells
+    pub fn preset(level: OptLevel) -> Self {
+        let names: &[&str] = match level {
+            OptLevel::O0 => &[],
+            OptLevel::O1 => &["fold-runs", "clear-loops", "dead-code"],
+            OptLevel::O2 => &PASS_NAMES,
+        };
+        Pipeline::from_names(names).expect("presets only use built-in passes")
+    }
+
+    /// The pipeline for an optimisation level, keeping the behaviour on the
+    /// given machine
+    pub fn for_machine(level: OptLevel, config: &MachineConfig) -> Self {
+        let mut pipeline = Pipeline::preset(level);
+        for pass in &mut pipeline.passes {
+            if pass.name() == "fold-runs" {
+                *pass = Box::new(FoldRuns {
+                    overflow: config.overflow,
+                });
+            }
+        }
+        pipeline
+    }
+
+    /// A pipeline running the named built-in passes in the given order
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let passes = names
+            .iter()
+            .map(|name| {
+                let name = name.as_ref();
+                pass_by_name(name).ok_or_else(|| format!("unknown pass `{name}`"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Pipeline { passes })
+    }
+
+    /// Append a pass to the end of the pipeline
+    pub fn with_pass(mut self, pass: impl OptimizationPass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Drop every pass with the given name
+    pub fn without(mut self, name: &str) -> Self {
+        self.passes.retain(|pass| pass.name() != name);
+        self
+    }
+
+    /// Names of the passes, in the order they run
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Run all passes in order, reporting what each of them did
+    pub fn run(&self, mut ops: Vec<Op>) -> (Vec<Op>, Report) {
+        let mut report = Report::default();
+        for pass in &self.passes {
+            let ops_before = count_ops(&ops);
+            let start = Instant::now();
+            ops = pass.run(ops);
+            report.passes.push(PassStats {
+                name: pass.name(),
+                ops_before,
+                ops_after: count_ops(&ops),
+                duration: start.elapsed(),
+            });
+        }
+        (ops, report)
+    }
+
+    /// Translate instructions and run all passes over them
+    pub fn optimize(&self, instructions: &[Instruction]) -> (Vec<Op>, Report) {
+        self.run(ir::translate(instructions))
+    }
+}
+
+/// Number of operations at any depth, a loop counting as one
+pub fn count_ops(ops: &[Op]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            Op::Loop(body) => 1 + count_ops(body),
+            _ => 1,
+        })
+        .sum()
+}
+
+/// What a single pass did
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PassStats {
+    pub name: &'static str,
+    pub ops_before: usize,
+    pub ops_after: usize,
+    pub duration: Duration,
+}
+
+/// What every pass of a pipeline run did, in order
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Report {
+    pub passes: Vec<PassStats>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>8} {:>8} {:>10}",
+            "pass", "before", "after", "time"
+        )?;
+        for pass in &self.passes {
+            writeln!(
+                f,
+                "{:<16} {:>8} {:>8} {:>10.1?}",
+                pass.name, pass.ops_before, pass.ops_after, pass.duration
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Interpreter};
+
+    const PROGRAMS: [(&str, &[u8]); 3] = [
+        (include_str!("../programs/hello_world.bf"), b""),
+        (include_str!("../programs/rot13.bf"), b"Hello, World!"),
+        ("[.]>>++[-<+++>][-]<[->+>+<<]>>[<]<.", b""),
+    ];
+
+    #[test]
+    fn test_presets() {
+        // Higher levels produce fewer operations
+        let instructions = parse(PROGRAMS[1].0).unwrap();
+        let (o0, _) = Pipeline::preset(OptLevel::O0).optimize(&instructions);
+        let (o1, _) = Pipeline::preset(OptLevel::O1).optimize(&instructions);
+        let (o2, _) = Pipeline::preset(OptLevel::O2).optimize(&instructions);
+        assert_eq!(o0, ir::translate(&instructions));
+        assert!(count_ops(&o1) < count_ops(&o0));
+        assert!(count_ops(&o2) < count_ops(&o1));
+        assert_eq!(o2, ir::lower(&instructions));
+    }
+
+    #[test]
+    fn test_every_pass_keeps_behaviour() {
+        // Each pass on its own and every preset match the interpreter
+        let mut pipelines: Vec<_> = PASS_NAMES
+            .iter()
+            .map(|name| Pipeline::from_names(&[name]).unwrap())
+            .collect();
+        pipelines.extend([OptLevel::O0, OptLevel::O1, OptLevel::O2].map(Pipeline::preset));
+        for (source, input) in PROGRAMS {
+            let instructions = parse(source).unwrap();
+            let mut reference = Interpreter::new(input, Vec::new());
+            reference.run_instructions(&instructions).unwrap();
+            let (_, _, expected) = reference.into_parts();
+            for pipeline in &pipelines {
+                let (ops, _) = pipeline.optimize(&instructions);
+                let mut optimised = Interpreter::new(input, Vec::new());
+                optimised.run_ops(&ops).unwrap();
+                let (_, _, output) = optimised.into_parts();
+                assert_eq!(output, expected, "{:?}", pipeline.pass_names());
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipeline_by_name() {
+        // Passes can be picked, reordered and dropped by name
+        let pipeline = Pipeline::from_names(&["dead-code", "fold-runs"]).unwrap();
+        assert_eq!(pipeline.pass_names(), ["dead-code", "fold-runs"]);
+        let pipeline = Pipeline::preset(OptLevel::O2).without("fold-offsets");
+        assert!(!pipeline.pass_names().contains(&"fold-offsets"));
+        assert!(Pipeline::from_names(&["nope"]).is_err());
+        assert_eq!("-O1".parse(), Ok(OptLevel::O1));
+        assert!("3".parse::<OptLevel>().is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        // One entry per pass with operation counts before and after
+        let instructions = parse("[-]+++>>--<<").unwrap();
+        let (_, report) = Pipeline::preset(OptLevel::O1).optimize(&instructions);
+        let counts: Vec<_> = report
+            .passes
+            .iter()
+            .map(|pass| (pass.name, pass.ops_before, pass.ops_after))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("fold-runs", 11, 6),
+                ("clear-loops", 6, 5),
+                ("dead-code", 5, 4),
+            ]
+        );
+        assert!(report.to_string().starts_with("pass"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::{fmt, ops::Deref, str::FromStr};
+
+use crate::{parse, BrainfuckProgram, Instruction, ParseError};
+
+impl BrainfuckProgram {
+    /// Wrap already parsed instructions
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        BrainfuckProgram(instructions)
+    }
+
+    /// The top-level instructions of the program
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.0
+    }
+
+    /// Unwrap the program into its top-level instructions
+    pub fn into_instructions(self) -> Vec<Instruction> {
+        self.0
+    }
+
+    /// Number of top-level instructions, a loop counting as one
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the program has no instructions at all
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Deepest loop nesting, 0 for programs without loops
+    pub fn depth(&self) -> usize {
+        fn depth(instructions: &[Instruction]) -> usize {
+            instructions
+                .iter()
+                .map(|instruction| match instruction {
+                    Instruction::Loop(body) => 1 + depth(body),
+                    _ => 0,
+                })
+                .max()
+                .unwrap_or(0)
+        }
+        depth(&self.0)
+    }
+
+    /// Number of instructions at any depth, a loop counting as one
+    pub fn instruction_count(&self) -> usize {
+        fn count(instructions: &[Instruction]) -> usize {
+            instructions
+                .iter()
+                .map(|instruction| match instruction {
+                    Instruction::Loop(body) => 1 + count(body),
+                    _ => 1,
+                })
+                .sum()
+        }
+        count(&self.0)
+    }
+
+    /// Length of the canonical source, i.e. the number of commands
+    /// including both brackets of every loop
+    pub fn source_len(&self) -> usize {
+        fn len(instructions: &[Instruction]) -> usize {
+            instructions
+                .iter()
+                .map(|instruction| match instruction {
+                    Instruction::Loop(body) => 2 + len(body),
+                    _ => 1,
+                })
+                .sum()
+        }
+        len(&self.0)
+    }
+}
+
+impl From<Vec<Instruction>> for BrainfuckProgram {
+    fn from(instructions: Vec<Instruction>) -> Self {
+        BrainfuckProgram(instructions)
+    }
+}
+
+impl FromStr for BrainfuckProgram {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s).map(BrainfuckProgram)
+    }
+}
+
+impl TryFrom<&str> for BrainfuckProgram {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Deref for BrainfuckProgram {
+    type Target = [Instruction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for BrainfuckProgram {
+    type Item = Instruction;
+    type IntoIter = std::vec::IntoIter<Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a BrainfuckProgram {
+    type Item = &'a Instruction;
+    type IntoIter = std::slice::Iter<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Prints the instruction as source code
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::RightShift => write!(f, ">"),
+            Instruction::LeftShift => write!(f, "<"),
+            Instruction::Increment => write!(f, "+"),
+            Instruction::Decrement => write!(f, "-"),
+            Instruction::Output => write!(f, "."),
+            Instruction::Input => write!(f, ","),
+            Instruction::Loop(body) => {
+                write!(f, "[")?;
+                for instruction in body {
+                    write!(f, "{instruction}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// Prints canonical source code, i.e. only commands without any comments
+impl fmt::Display for BrainfuckProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.0 {
+            write!(f, "{instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    #[test]
+    fn test_program_round_trip() {
+        // Displaying a parsed program gives back its commands
+        let program: BrainfuckProgram = HELLO_WORLD.parse().unwrap();
+        assert_eq!(program.to_string(), HELLO_WORLD);
+        let commented =
+            BrainfuckProgram::try_from(include_str!("../programs/hello_world.bf")).unwrap();
+        assert_eq!(commented, program);
+    }
+
+    #[test]
+    fn test_program_parse_error() {
+        // Parse errors are passed on unchanged
+        assert!(matches!(
+            "[".parse::<BrainfuckProgram>(),
+            Err(ParseError::UnmatchedOpen { .. })
+        ));
+    }
+
+    #[test]
+    fn test_program_helpers() {
+        // Sizes and nesting of a small program
+        let program: BrainfuckProgram = "+[->[-]<]>.".parse().unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(program.depth(), 2);
+        assert_eq!(program.instruction_count(), 9);
+        assert_eq!(program.source_len(), program.to_string().len());
+        assert_eq!(program[0], Instruction::Increment);
+        assert_eq!(program.iter().count(), 4);
+        assert!(BrainfuckProgram::default().is_empty());
+        assert_eq!(BrainfuckProgram::default().depth(), 0);
+    }
+}
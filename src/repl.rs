@@ -0,0 +1,368 @@
+//! Interactive read-eval-print loop
+//!
+//! Every line is parsed and run on a tape that lives as long as the [`Repl`],
+//! so cells and the data pointer carry over from one line to the next. A
+//! line leaving a loop open is kept and continued by the following lines.
+//! Lines starting with `:` are meta-commands, see [`HELP`].
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    mem,
+};
+
+use crate::{
+    parse, BrainfuckProgram, ExecError, Interpreter, MachineConfig, ParseError, Tape, TapeLength,
+};
+
+/// Meta-commands the REPL understands
+pub const HELP: &str = ":tape                  show all cells up to the last one in use
+:ptr                   show the data pointer and the current cell
+:reset                 start over on a fresh tape
+:load <file>           run a file on the tape
+:config                show the machine model
+:config <name> <value> change cell-width, overflow, tape or eof, resetting the tape
+:help                  show this help
+:quit                  leave";
+
+/// Cells shown on each side of the pointer after running a line
+const RADIUS: isize = 4;
+
+/// What to show after a line
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    /// A loop is still open, the next line continues it
+    Continue,
+    /// Text for the user, like the cells around the pointer
+    Message(String),
+    /// The user wants to leave
+    Quit,
+}
+
+/// Everything that can go wrong with a line
+#[derive(Debug)]
+pub enum ReplError {
+    Parse(ParseError),
+    Exec(ExecError),
+    /// A meta-command that does not exist or has wrong arguments
+    Command(String),
+    /// A file for `:load` could not be read
+    Load(String, io::Error),
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplError::Parse(e) => write!(f, "{e}"),
+            ReplError::Exec(e) => write!(f, "{e}"),
+            ReplError::Command(message) => write!(f, "{message}"),
+            ReplError::Load(path, e) => write!(f, "cannot read {path}: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplError {}
+
+/// State of an interactive session
+pub struct Repl {
+    tape: Tape,
+    /// Lines of a loop that is still open
+    pending: String,
+}
+
+impl Repl {
+    /// A session on a fresh tape for the given machine model
+    pub fn new(config: &MachineConfig) -> Self {
+        Repl {
+            tape: Tape::new(config),
+            pending: String::new(),
+        }
+    }
+
+    /// The tape in its current state
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    /// Prompt for the next line, different while a loop is open
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            "bf> "
+        } else {
+            "... "
+        }
+    }
+
+    /// Handle one line, running code with `input` and `output`
+    ///
+    /// If running fails, the tape keeps whatever the code did up to there.
+    pub fn eval(
+        &mut self,
+        line: &str,
+        input: impl Read,
+        output: impl Write,
+    ) -> Result<Response, ReplError> {
+        if let Some(command) = line.trim().strip_prefix(':') {
+            return self.command(command, input, output);
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        // An open loop is the one error that more lines can fix
+        let source = mem::take(&mut self.pending);
+        match parse(&source) {
+            Err(ParseError::UnmatchedOpen { .. }) => {
+                self.pending = source;
+                Ok(Response::Continue)
+            }
+            Err(e) => Err(ReplError::Parse(e)),
+            Ok(instructions) => self.run(&BrainfuckProgram::new(instructions), input, output),
+        }
+    }
+
+    fn command(
+        &mut self,
+        command: &str,
+        input: impl Read,
+        output: impl Write,
+    ) -> Result<Response, ReplError> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let message = match (name, args.as_slice()) {
+            ("tape", []) => {
+                let pointer = self.tape.pointer();
+                let used: Vec<isize> = self
+                    .positions()
+                    .filter(|&position| self.value(position) != 0)
+                    .collect();
+                let first = used.first().map_or(0, |&first| first.min(0));
+                let last = used.last().map_or(pointer, |&last| last.max(pointer));
+                self.cells(first.min(pointer), last)
+            }
+            ("ptr", []) => format!(
+                "pointer at cell {}, holding {}",
+                self.tape.pointer(),
+                self.tape.get()
+            ),
+            ("reset", []) => {
+                *self = Repl::new(self.tape.config());
+                "fresh tape".to_string()
+            }
+            ("load", [path]) => {
+                let source =
+                    fs::read_to_string(path).map_err(|e| ReplError::Load(path.to_string(), e))?;
+                let program = source.parse().map_err(ReplError::Parse)?;
+                return self.run(&program, input, output);
+            }
+            ("config", []) => describe(self.tape.config()),
+            ("config", [name, value]) => {
+                let mut config = *self.tape.config();
+                match *name {
+                    "cell-width" => {
+                        config.cell_width = value.parse().map_err(ReplError::Command)?
+                    }
+                    "overflow" => config.overflow = value.parse().map_err(ReplError::Command)?,
+                    "tape" => config.tape_length = value.parse().map_err(ReplError::Command)?,
+                    "eof" => config.eof = value.parse().map_err(ReplError::Command)?,
+                    _ => return Err(ReplError::Command(format!("unknown setting `{name}`"))),
+                }
+                *self = Repl::new(&config);
+                format!("{}, on a fresh tape", describe(&config))
+            }
+            ("help", []) => HELP.to_string(),
+            ("quit" | "q", []) => return Ok(Response::Quit),
+            _ => {
+                return Err(ReplError::Command(format!(
+                    "unknown command `:{command}`, see `:help`"
+                )))
+            }
+        };
+        Ok(Response::Message(message))
+    }
+
+    /// Run a program on the tape and show the cells around the pointer
+    fn run(
+        &mut self,
+        program: &BrainfuckProgram,
+        input: impl Read,
+        output: impl Write,
+    ) -> Result<Response, ReplError> {
+        let mut output = LastByte {
+            inner: output,
+            last: None,
+        };
+        let tape = mem::take(&mut self.tape);
+        let mut interpreter = Interpreter::with_tape(tape, input, &mut output);
+        let result = interpreter.run(program);
+        self.tape = interpreter.into_parts().0;
+        result.map_err(ReplError::Exec)?;
+
+        let pointer = self.tape.pointer();
+        let mut message = self.cells(pointer - RADIUS, pointer + RADIUS);
+        // Keep the cells off a line the program did not finish
+        if output.last.is_some_and(|byte| byte != b'\n') {
+            message.insert(0, '\n');
+        }
+        Ok(Response::Message(message))
+    }
+
+    /// Cells from `first` to `last`, the current one in brackets, leaving
+    /// out positions that are not on the tape
+    pub fn cells(&self, first: isize, last: isize) -> String {
+        let (first, last) = match self.tape.config().tape_length {
+            TapeLength::Fixed(len) => (first.max(0), last.min(len as isize - 1)),
+            TapeLength::Growable => (first.max(0), last),
+            TapeLength::Unbounded => (first, last),
+        };
+        let values: Vec<String> = (first..=last)
+            .map(|position| {
+                let value = self.value(position);
+                if position == self.tape.pointer() {
+                    format!("[{value}]")
+                } else {
+                    value.to_string()
+                }
+            })
+            .collect();
+        format!("cells {first} to {last}: {}", values.join(" "))
+    }
+
+    /// Positions of all allocated cells
+    fn positions(&self) -> impl Iterator<Item = isize> {
+        let first = self.tape.first_position();
+        first..first + self.tape.cells().len() as isize
+    }
+
+    fn value(&self, position: isize) -> i64 {
+        self.tape.get_at(position - self.tape.pointer())
+    }
+}
+
+/// The machine model as `:config` settings
+fn describe(config: &MachineConfig) -> String {
+    format!(
+        "cell-width {}, overflow {}, tape {}, eof {}",
+        config.cell_width, config.overflow, config.tape_length, config.eof
+    )
+}
+
+/// Remembers the last byte written, to know whether output ended a line
+struct LastByte<W> {
+    inner: W,
+    last: Option<u8>,
+}
+
+impl<W: Write> Write for LastByte<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if written > 0 {
+            self.last = Some(buf[written - 1]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluate a line without input, giving back the response and output
+    fn eval(repl: &mut Repl, line: &str) -> (Response, String) {
+        let mut output = Vec::new();
+        let response = repl.eval(line, &[][..], &mut output).unwrap();
+        (response, String::from_utf8(output).unwrap())
+    }
+
+    fn message(text: &str) -> Response {
+        Response::Message(text.to_string())
+    }
+
+    #[test]
+    fn test_tape_persists() {
+        // Each line continues where the last one left off
+        let mut repl = Repl::new(&MachineConfig::default());
+        assert_eq!(
+            eval(&mut repl, "+++>++").0,
+            message("cells 0 to 5: 3 [2] 0 0 0 0")
+        );
+        assert_eq!(
+            eval(&mut repl, "<[->+<]>").0,
+            message("cells 0 to 5: 0 [5] 0 0 0 0")
+        );
+        assert_eq!(
+            eval(&mut repl, ":ptr").0,
+            message("pointer at cell 1, holding 5")
+        );
+        assert_eq!(
+            eval(&mut repl, ">>+").0,
+            message("cells 0 to 7: 0 5 0 [1] 0 0 0 0")
+        );
+        assert_eq!(
+            eval(&mut repl, ":tape").0,
+            message("cells 0 to 3: 0 5 0 [1]")
+        );
+        assert_eq!(eval(&mut repl, ":reset").0, message("fresh tape"));
+        assert_eq!(eval(&mut repl, ":tape").0, message("cells 0 to 0: [0]"));
+    }
+
+    #[test]
+    fn test_multi_line_loop() {
+        // Lines are collected until every loop is closed
+        let mut repl = Repl::new(&MachineConfig::default());
+        assert_eq!(eval(&mut repl, "++++++++[>++++++++").0, Response::Continue);
+        assert_eq!(repl.prompt(), "... ");
+        assert_eq!(eval(&mut repl, "  [-]<").0, Response::Continue);
+        assert_eq!(eval(&mut repl, "-]>+.").1, "\x01");
+        assert_eq!(repl.prompt(), "bf> ");
+        assert!(matches!(
+            repl.eval("]", &[][..], Vec::new()),
+            Err(ReplError::Parse(ParseError::UnmatchedClose { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_output_and_errors() {
+        // Program output without a newline is followed by one, and a failing
+        // line keeps what it did up to the error
+        let mut repl = Repl::new(&MachineConfig::default());
+        let mut output = Vec::new();
+        let response = repl.eval(",.", &b"A"[..], &mut output).unwrap();
+        assert_eq!(output, b"A");
+        assert_eq!(response, message("\ncells 0 to 4: [65] 0 0 0 0"));
+        assert!(matches!(
+            repl.eval("+<", &[][..], Vec::new()),
+            Err(ReplError::Exec(ExecError::PointerOutOfBounds { .. }))
+        ));
+        assert_eq!(repl.tape().get(), 66);
+    }
+
+    #[test]
+    fn test_config() {
+        // Changing the machine starts on a fresh tape
+        let mut repl = Repl::new(&MachineConfig::default());
+        eval(&mut repl, "+");
+        assert_eq!(
+            eval(&mut repl, ":config").0,
+            message("cell-width 8, overflow wrap, tape 30000, eof unchanged")
+        );
+        assert_eq!(
+            eval(&mut repl, ":config tape unbounded").0,
+            message("cell-width 8, overflow wrap, tape unbounded, eof unchanged, on a fresh tape")
+        );
+        assert_eq!(
+            eval(&mut repl, "<-").0,
+            message("cells -5 to 3: 0 0 0 0 [255] 0 0 0 0")
+        );
+        for line in [":config tape none", ":config speed 3", ":frobnicate"] {
+            assert!(matches!(
+                repl.eval(line, &[][..], Vec::new()),
+                Err(ReplError::Command(_))
+            ));
+        }
+        assert_eq!(eval(&mut repl, ":quit").0, Response::Quit);
+    }
+}
@@ -0,0 +1,181 @@
+use std::str::CharIndices;
+
+use crate::{Instruction, COMMANDS};
+
+/// A location in the source code
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// Byte offset from the start of the source
+    pub offset: usize,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column in characters, starting at 1
+    pub column: usize,
+}
+
+/// An [`Instruction`] together with where it was written in the source
+#[derive(Debug, PartialEq, Clone)]
+pub enum SpannedInstruction {
+    /// Any instruction but a loop, found at `at`
+    Command {
+        instruction: Instruction,
+        at: Position,
+    },
+    /// A loop with the positions of its opening and closing bracket
+    Loop {
+        body: Vec<SpannedInstruction>,
+        open: Position,
+        close: Position,
+    },
+}
+
+impl SpannedInstruction {
+    /// Position of the instruction, i.e. of the `[` for loops
+    pub fn position(&self) -> Position {
+        match self {
+            SpannedInstruction::Command { at, .. } => *at,
+            SpannedInstruction::Loop { open, .. } => *open,
+        }
+    }
+
+    /// Drop all spans, giving back the plain instruction
+    pub fn strip(&self) -> Instruction {
+        match self {
+            SpannedInstruction::Command { instruction, .. } => instruction.clone(),
+            SpannedInstruction::Loop { body, .. } => Instruction::Loop(strip_spans(body)),
+        }
+    }
+}
+
+/// Drop all spans from a spanned tree, giving back the plain instructions
+pub fn strip_spans(instructions: &[SpannedInstruction]) -> Vec<Instruction> {
+    instructions.iter().map(SpannedInstruction::strip).collect()
+}
+
+/// Walks the commands of a source in order while tracking line and column
+struct Cursor<'a> {
+    chars: CharIndices<'a>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str) -> Self {
+        Cursor {
+            chars: source.char_indices(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Position of the next command, skipping everything else
+    fn next_command(&mut self) -> Position {
+        for (offset, c) in self.chars.by_ref() {
+            let position = Position {
+                offset,
+                line: self.line,
+                column: self.column,
+            };
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            if COMMANDS.contains(c) {
+                return position;
+            }
+        }
+        unreachable!("source has fewer commands than the parsed instructions")
+    }
+}
+
+/// Attach source positions to instructions that were parsed from `source`
+///
+/// This relies on the commands appearing in the source in the same order as
+/// a pre-order walk of the tree, with each `]` following its loop body.
+pub(crate) fn attach_spans(
+    source: &str,
+    instructions: Vec<Instruction>,
+) -> Vec<SpannedInstruction> {
+    attach_body(&mut Cursor::new(source), instructions)
+}
+
+fn attach_body(cursor: &mut Cursor, instructions: Vec<Instruction>) -> Vec<SpannedInstruction> {
+    instructions
+        .into_iter()
+        .map(|instruction| match instruction {
+            Instruction::Loop(body) => {
+                let open = cursor.next_command();
+                let body = attach_body(cursor, body);
+                let close = cursor.next_command();
+                SpannedInstruction::Loop { body, open, close }
+            }
+            instruction => SpannedInstruction::Command {
+                instruction,
+                at: cursor.next_command(),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_spanned};
+
+    fn at(offset: usize, line: usize, column: usize) -> Position {
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn test_spans_basic() {
+        // Offsets, lines and columns of plain commands
+        let spanned = parse_spanned("+\n >").unwrap();
+        assert_eq!(
+            spanned,
+            vec![
+                SpannedInstruction::Command {
+                    instruction: Instruction::Increment,
+                    at: at(0, 1, 1),
+                },
+                SpannedInstruction::Command {
+                    instruction: Instruction::RightShift,
+                    at: at(3, 2, 2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans_loop_brackets() {
+        // Loops know where both of their brackets are
+        let spanned = parse_spanned("a [\n-\n] b").unwrap();
+        let SpannedInstruction::Loop { body, open, close } = &spanned[0] else {
+            panic!("expected a loop");
+        };
+        assert_eq!(*open, at(2, 1, 3));
+        assert_eq!(*close, at(6, 3, 1));
+        assert_eq!(body[0].position(), at(4, 2, 1));
+    }
+
+    #[test]
+    fn test_spans_multibyte_comments() {
+        // Columns count characters while offsets count bytes
+        let spanned = parse_spanned("äö+").unwrap();
+        assert_eq!(spanned[0].position(), at(4, 1, 3));
+    }
+
+    #[test]
+    fn test_strip_spans() {
+        // Stripping spans gives back exactly what `parse` returns
+        let source = include_str!("../programs/rot13.bf");
+        let spanned = parse_spanned(source).unwrap();
+        let plain = parse(source).unwrap();
+        assert_eq!(strip_spans(&spanned), plain);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{ExecError, MachineConfig, Overflow, TapeLength};
+
+/// Number of cells of the classic brainfuck tape
+pub const DEFAULT_TAPE_LEN: usize = 30_000;
+
+/// Number of cells allocated up front for tapes that grow
+const INITIAL_GROWABLE_LEN: usize = 1024;
+
+/// The memory of a brainfuck machine together with its data pointer
+///
+/// Cells are addressed by their position relative to the starting cell,
+/// which can be negative on [`TapeLength::Unbounded`] tapes. Values are kept
+/// as `i64` so that every [`CellWidth`](crate::CellWidth) fits.
+#[derive(Debug, Clone)]
+pub struct Tape {
+    cells: Vec<i64>,
+    /// Index into `cells` of position 0
+    origin: usize,
+    pointer: isize,
+    config: MachineConfig,
+    /// Number of cells the tape may grow to
+    max_len: Option<usize>,
+}
+
+/// Tapes are equal when they follow the same model and hold the same values,
+/// no matter how far they have grown
+impl PartialEq for Tape {
+    fn eq(&self, other: &Self) -> bool {
+        let first = self.first_position().min(other.first_position());
+        let end = self.end_position().max(other.end_position());
+        self.config == other.config
+            && self.pointer == other.pointer
+            && (first..end).all(|position| {
+                self.get_at(position - self.pointer) == other.get_at(position - other.pointer)
+            })
+    }
+}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Tape::new(&MachineConfig::default())
+    }
+}
+
+impl Tape {
+    /// A zeroed tape for the given machine with the pointer on position 0
+    pub fn new(config: &MachineConfig) -> Self {
+        let len = match config.tape_length {
+            TapeLength::Fixed(len) => len,
+            TapeLength::Growable | TapeLength::Unbounded => INITIAL_GROWABLE_LEN,
+        };
+        Tape {
+            cells: vec![0; len],
+            origin: 0,
+            pointer: 0,
+            config: *config,
+            max_len: None,
+        }
+    }
+
+    /// A tape holding `cells` from position 0 on, with the pointer on `pointer`
+    #[cfg_attr(not(feature = "jit"), allow(dead_code))]
+    pub(crate) fn from_cells(config: &MachineConfig, cells: Vec<i64>, pointer: isize) -> Self {
+        Tape {
+            cells,
+            origin: 0,
+            pointer,
+            config: *config,
+            max_len: None,
+        }
+    }
+
+    /// The machine model the tape follows
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    /// Keep a growable or unbounded tape from growing past `max_len` cells,
+    /// growing any further failing with [`ExecError::TapeLimit`]
+    ///
+    /// If more cells than that are allocated already, unused ones at the
+    /// right end are given back.
+    pub fn set_max_len(&mut self, max_len: Option<usize>) {
+        self.max_len = max_len;
+        let fixed = matches!(self.config.tape_length, TapeLength::Fixed(_));
+        if !fixed && max_len.is_some_and(|max_len| self.cells.len() > max_len) {
+            let pointer = (self.origin as isize + self.pointer) as usize;
+            let used = self.cells.iter().rposition(|&cell| cell != 0).unwrap_or(0);
+            self.cells.truncate(used.max(pointer) + 1);
+        }
+    }
+
+    /// Position of the cell the data pointer is on
+    pub fn pointer(&self) -> isize {
+        self.pointer
+    }
+
+    /// All cells allocated so far, the first one being at [`Tape::first_position`]
+    pub fn cells(&self) -> &[i64] {
+        &self.cells
+    }
+
+    /// Position of the first allocated cell
+    pub fn first_position(&self) -> isize {
+        -(self.origin as isize)
+    }
+
+    /// Position just after the last allocated cell
+    fn end_position(&self) -> isize {
+        self.first_position() + self.cells.len() as isize
+    }
+
+    /// Value of the current cell
+    pub fn get(&self) -> i64 {
+        self.get_at(0)
+    }
+
+    /// Value of the cell `offset` cells away from the pointer
+    ///
+    /// Cells that were never allocated are read as 0.
+    pub fn get_at(&self, offset: isize) -> i64 {
+        let index = self.origin as isize + self.pointer + offset;
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.cells.get(index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Overwrite the current cell, wrapping the value into range
+    pub fn set(&mut self, value: i64) -> Result<(), ExecError> {
+        self.set_at(0, value)
+    }
+
+    /// Overwrite the cell `offset` cells away from the pointer
+    pub fn set_at(&mut self, offset: isize, value: i64) -> Result<(), ExecError> {
+        let index = self.index(self.pointer + offset)?;
+        self.cells[index] = self.config.cell_width.wrap(value);
+        Ok(())
+    }
+
+    /// Add to the current cell, handling overflow as configured
+    pub fn add(&mut self, delta: i64) -> Result<(), ExecError> {
+        self.add_at(0, delta)
+    }
+
+    /// Add to the cell `offset` cells away from the pointer
+    pub fn add_at(&mut self, offset: isize, delta: i64) -> Result<(), ExecError> {
+        let position = self.pointer + offset;
+        let index = self.index(position)?;
+        let cell = self.cells[index];
+        let (min, max) = (self.config.cell_width.min(), self.config.cell_width.max());
+        self.cells[index] = match self.config.overflow {
+            // Only the low bits survive wrapping, so overflowing `i64` is fine
+            Overflow::Wrap => self.config.cell_width.wrap(cell.wrapping_add(delta)),
+            Overflow::Saturate => cell.saturating_add(delta).clamp(min, max),
+            Overflow::Error => cell
+                .checked_add(delta)
+                .filter(|value| (min..=max).contains(value))
+                .ok_or(ExecError::CellOverflow { position })?,
+        };
+        Ok(())
+    }
+
+    /// Add the current cell times `factor` to the cell `offset` cells away
+    pub(crate) fn add_product(&mut self, offset: isize, factor: i32) -> Result<(), ExecError> {
+        let value = self.get();
+        let factor = i64::from(factor);
+        // Only the low bits matter when wrapping, otherwise the overflow
+        // policy needs to see the true sign and size
+        let product = match self.config.overflow {
+            Overflow::Wrap => value.wrapping_mul(factor),
+            _ => value.saturating_mul(factor),
+        };
+        self.add_at(offset, product)
+    }
+
+    /// Store a byte of input in the cell `offset` cells away, `None` being
+    /// the end of input where the EOF policy applies, giving back whether
+    /// the cell was written
+    pub(crate) fn store_input(
+        &mut self,
+        offset: isize,
+        byte: Option<u8>,
+    ) -> Result<bool, ExecError> {
+        let value = match byte {
+            Some(byte) => byte.into(),
+            None => match self.config.eof_value() {
+                Some(value) => value,
+                None => return Ok(false),
+            },
+        };
+        self.set_at(offset, value)?;
+        Ok(true)
+    }
+
+    /// Move the data pointer, failing if it would leave the tape
+    pub fn move_by(&mut self, delta: isize) -> Result<(), ExecError> {
+        let pointer = self.pointer + delta;
+        self.index(pointer)?;
+        self.pointer = pointer;
+        Ok(())
+    }
+
+    /// Index into `cells` of `position`, growing the tape if allowed
+    fn index(&mut self, position: isize) -> Result<usize, ExecError> {
+        let index = self.origin as isize + position;
+        if index >= 0 && (index as usize) < self.cells.len() {
+            return Ok(index as usize);
+        }
+        self.grow(position)
+    }
+
+    /// Make room for `position` if the tape can grow there
+    #[cold]
+    fn grow(&mut self, position: isize) -> Result<usize, ExecError> {
+        let out_of_bounds = ExecError::PointerOutOfBounds { pointer: position };
+        let index = self.origin as isize + position;
+        if index < 0 {
+            if self.config.tape_length != TapeLength::Unbounded {
+                return Err(out_of_bounds);
+            }
+            // Grow to the left by at least doubling to keep this amortised
+            let needed = -index as usize;
+            let grow = needed
+                .max(self.cells.len())
+                .min(self.room(position, needed)?);
+            let mut cells = vec![0; grow];
+            cells.append(&mut self.cells);
+            self.cells = cells;
+            self.origin += grow;
+            return Ok((self.origin as isize + position) as usize);
+        }
+        let index = index as usize;
+        if index >= self.cells.len() {
+            if let TapeLength::Fixed(_) = self.config.tape_length {
+                return Err(out_of_bounds);
+            }
+            let needed = index + 1 - self.cells.len();
+            let grow = needed
+                .max(self.cells.len())
+                .min(self.room(position, needed)?);
+            let len = self.cells.len() + grow;
+            self.cells.resize(len, 0);
+        }
+        Ok(index)
+    }
+
+    /// Cells the tape may still grow by, at least the `needed` ones to
+    /// reach `position`
+    fn room(&self, position: isize, needed: usize) -> Result<usize, ExecError> {
+        let Some(max_len) = self.max_len else {
+            return Ok(usize::MAX);
+        };
+        let room = max_len.saturating_sub(self.cells.len());
+        if needed > room {
+            return Err(ExecError::TapeLimit { position });
+        }
+        Ok(room)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CellWidth, EofBehavior};
+
+    fn tape(cell_width: CellWidth, overflow: Overflow, tape_length: TapeLength) -> Tape {
+        Tape::new(&MachineConfig {
+            cell_width,
+            overflow,
+            tape_length,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_tape_overflow() {
+        // Each overflow policy at the top of a u8 cell
+        let mut wrap = tape(CellWidth::U8, Overflow::Wrap, TapeLength::Fixed(1));
+        wrap.add(256 + 3).unwrap();
+        assert_eq!(wrap.get(), 3);
+
+        let mut saturate = tape(CellWidth::U8, Overflow::Saturate, TapeLength::Fixed(1));
+        saturate.add(300).unwrap();
+        assert_eq!(saturate.get(), 255);
+        saturate.add(-1000).unwrap();
+        assert_eq!(saturate.get(), 0);
+
+        let mut error = tape(CellWidth::U8, Overflow::Error, TapeLength::Fixed(1));
+        error.add(255).unwrap();
+        assert!(matches!(
+            error.add(1),
+            Err(ExecError::CellOverflow { position: 0 })
+        ));
+        assert_eq!(error.get(), 255);
+    }
+
+    #[test]
+    fn test_tape_signed_cells() {
+        // i32 cells go negative instead of wrapping at 0
+        let mut signed = tape(CellWidth::I32, Overflow::Error, TapeLength::Fixed(1));
+        signed.add(-5).unwrap();
+        assert_eq!(signed.get(), -5);
+    }
+
+    #[test]
+    fn test_tape_fixed_bounds() {
+        // Fixed tapes end on both sides
+        let mut fixed = tape(CellWidth::U8, Overflow::Wrap, TapeLength::Fixed(2));
+        fixed.move_by(1).unwrap();
+        assert!(fixed.move_by(1).is_err());
+        assert!(fixed.move_by(-2).is_err());
+        assert_eq!(fixed.pointer(), 1);
+    }
+
+    #[test]
+    fn test_tape_growable() {
+        // Growable tapes only grow to the right
+        let mut growable = tape(CellWidth::U8, Overflow::Wrap, TapeLength::Growable);
+        growable.move_by(100_000).unwrap();
+        growable.add(7).unwrap();
+        assert_eq!(growable.get_at(0), 7);
+        assert!(growable.move_by(-100_001).is_err());
+    }
+
+    #[test]
+    fn test_tape_unbounded() {
+        // Unbounded tapes grow both ways and keep their contents
+        let mut unbounded = tape(CellWidth::U8, Overflow::Wrap, TapeLength::Unbounded);
+        unbounded.add(1).unwrap();
+        unbounded.move_by(-5000).unwrap();
+        unbounded.add(2).unwrap();
+        assert_eq!(unbounded.get(), 2);
+        assert_eq!(unbounded.get_at(5000), 1);
+        assert!(unbounded.first_position() <= -5000);
+        assert_eq!(unbounded.pointer(), -5000);
+    }
+
+    #[test]
+    fn test_tape_input_and_products() {
+        // The EOF policy decides what the end of input writes, products
+        // follow the overflow policy
+        let mut tape = Tape::new(&MachineConfig {
+            eof: EofBehavior::MinusOne,
+            overflow: Overflow::Saturate,
+            ..Default::default()
+        });
+        assert!(tape.store_input(1, Some(b'a')).unwrap());
+        assert!(tape.store_input(0, None).unwrap());
+        assert_eq!(tape.cells()[..2], [255, 97]);
+        tape.add_product(2, 2).unwrap();
+        assert_eq!(tape.get_at(2), 255);
+        assert!(!Tape::default().store_input(0, None).unwrap());
+    }
+
+    #[test]
+    fn test_tape_max_len() {
+        // Growing stops at the limit on either side, cells in use are kept
+        let mut growable = tape(CellWidth::U8, Overflow::Wrap, TapeLength::Growable);
+        growable.move_by(200).unwrap();
+        growable.add(1).unwrap();
+        growable.set_max_len(Some(100));
+        assert_eq!(growable.cells().len(), 201);
+        assert!(growable.move_by(1).is_err());
+        growable.set_max_len(Some(500));
+        growable.move_by(299).unwrap();
+        assert_eq!(growable.cells().len(), 500);
+        assert!(matches!(
+            growable.move_by(1),
+            Err(ExecError::TapeLimit { position: 500 })
+        ));
+        assert_eq!(growable.pointer(), 499);
+
+        let mut unbounded = tape(CellWidth::U8, Overflow::Wrap, TapeLength::Unbounded);
+        unbounded.set_max_len(Some(10));
+        assert_eq!(unbounded.cells().len(), 1);
+        unbounded.move_by(-9).unwrap();
+        assert!(matches!(
+            unbounded.move_by(-1),
+            Err(ExecError::TapeLimit { position: -10 })
+        ));
+        assert_eq!(unbounded.cells().len(), 10);
+    }
+}
@@ -0,0 +1,590 @@
+//! Full-screen terminal front-end for the [`Debugger`]
+//!
+//! The screen shows the source with the next command highlighted, the tape
+//! around the data pointer, the input still to be read and the output so
+//! far. Keys step through the program, forwards or backwards, and toggle
+//! breakpoints on the command under a cursor that can be moved around the
+//! source. Commands that run for long go in parts with the screen redrawn
+//! in between, and any key interrupts them.
+
+use std::{
+    io::{self, Cursor, Write},
+    time::Duration,
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::{
+    debugger::{Debugger, Goal, Stop},
+    ExecError, TapeLength, COMMANDS,
+};
+
+/// A debugger reading its input from memory and collecting its output
+pub type Session = Debugger<Cursor<Vec<u8>>, Vec<u8>>;
+
+/// Keys and what they do, shown at the bottom of the screen
+const HELP: &str = "s step  n step over  o step out  c continue  p step back  C reverse continue  \
+    w last write  arrows move  b breakpoint  r restart  q quit";
+
+/// Commands run between looking for keys and redrawing while a command
+/// runs
+const CHUNK: u64 = 10_000;
+
+/// Lines of output shown
+const OUTPUT_LINES: usize = 3;
+
+/// Lines taken by everything but the source
+const FIXED_LINES: usize = 8 + OUTPUT_LINES;
+
+/// Width of a cell in the tape view, including the space in front
+const CELL_WIDTH: usize = 6;
+
+/// How a piece of text is shown
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Style {
+    Plain,
+    /// Section headings and the status bar
+    Title,
+    /// The next command and the current cell
+    Current,
+    /// The command under the cursor
+    Cursor,
+    /// Commands with a breakpoint
+    Breakpoint,
+    /// Line numbers and help
+    Dim,
+}
+
+/// A line of the screen as pieces of styled text
+type Line = Vec<(Style, String)>;
+
+/// Debug a program until the user quits
+pub fn run(session: Session) -> io::Result<()> {
+    let mut app = App::new(session);
+    let mut stdout = io::stdout();
+    let _screen = Screen::enter(&mut stdout)?;
+    loop {
+        let (width, height) = terminal::size()?;
+        draw(&mut stdout, &app.render(width.into(), height.into()))?;
+        if app.running.is_some() && !event::poll(Duration::ZERO)? {
+            app.tick();
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            // Raw mode turns Ctrl-C into a key, which stops like escape
+            let code = match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Esc,
+                code => code,
+            };
+            if key.kind == KeyEventKind::Press && !app.key(code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Puts the terminal back the way it was when dropped
+struct Screen;
+
+impl Screen {
+    fn enter(stdout: &mut impl Write) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(stdout: &mut impl Write, lines: &[Line]) -> io::Result<()> {
+    for (row, line) in lines.iter().enumerate() {
+        queue!(stdout, MoveTo(0, row as u16))?;
+        for (style, text) in line {
+            match style {
+                Style::Plain => {}
+                Style::Title => queue!(stdout, SetAttribute(Attribute::Bold))?,
+                Style::Current => queue!(stdout, SetAttribute(Attribute::Reverse))?,
+                Style::Cursor => queue!(stdout, SetAttribute(Attribute::Underlined))?,
+                Style::Breakpoint => queue!(stdout, SetForegroundColor(Color::Red))?,
+                Style::Dim => queue!(stdout, SetAttribute(Attribute::Dim))?,
+            }
+            queue!(
+                stdout,
+                Print(text),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )?;
+        }
+        queue!(stdout, Clear(ClearType::UntilNewLine))?;
+    }
+    queue!(stdout, Clear(ClearType::FromCursorDown))?;
+    stdout.flush()
+}
+
+/// The debugger together with what the screen shows about it
+struct App {
+    session: Session,
+    /// Byte offset of the command under the cursor
+    cursor: usize,
+    /// What happened last
+    status: String,
+    /// Goal of a command running in parts, until it gets there or a key
+    /// interrupts it
+    running: Option<Goal>,
+}
+
+impl App {
+    fn new(session: Session) -> Self {
+        let mut app = App {
+            session,
+            cursor: 0,
+            status: "ready".to_string(),
+            running: None,
+        };
+        app.follow();
+        app
+    }
+
+    /// Handle a key, giving back whether to go on
+    ///
+    /// Any key interrupts a running command instead of doing what it does.
+    fn key(&mut self, key: KeyCode) -> bool {
+        if self.running.take().is_some() {
+            self.status = "interrupted".to_string();
+            self.follow();
+            return true;
+        }
+        match key {
+            KeyCode::Char('s') | KeyCode::Char(' ') => self.pursue(Goal::Step),
+            KeyCode::Char('n') => self.pursue(self.session.over()),
+            KeyCode::Char('o') => self.pursue(self.session.out()),
+            KeyCode::Char('c') => self.pursue(Goal::End),
+            KeyCode::Char('p') => {
+                let result = self.session.step_back();
+                self.report(result);
+            }
+            KeyCode::Char('C') => self.pursue(Goal::Start),
+            KeyCode::Char('w') => self.last_write(),
+            KeyCode::Char('b') => self.toggle_breakpoint(),
+            KeyCode::Char('r') => self.restart(),
+            KeyCode::Left => self.move_cursor(-1),
+            KeyCode::Right => self.move_cursor(1),
+            KeyCode::Up => self.move_line(-1),
+            KeyCode::Down => self.move_line(1),
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Start running towards `goal`, a part at a time
+    fn pursue(&mut self, goal: Goal) {
+        self.running = Some(goal);
+        self.tick();
+    }
+
+    /// Run the next part of the running command
+    fn tick(&mut self) {
+        let Some(goal) = self.running else {
+            return;
+        };
+        let result = self.session.pursue(goal, CHUNK);
+        if !matches!(result, Ok(Stop::Limit)) {
+            self.running = None;
+        }
+        self.report(result);
+    }
+
+    /// Tell why the debugger stopped
+    fn report(&mut self, result: Result<Stop, ExecError>) {
+        self.status = match result {
+            Ok(Stop::Step) => "stepped".to_string(),
+            Ok(Stop::Breakpoint(at)) => {
+                format!("breakpoint at line {}, column {}", at.line, at.column)
+            }
+            Ok(Stop::Watchpoint(watchpoint)) => format!("watchpoint {watchpoint:?}"),
+            Ok(Stop::Finished) => "finished".to_string(),
+            Ok(Stop::Start) => "at the oldest recorded step".to_string(),
+            Ok(Stop::Limit) => "running, any key interrupts".to_string(),
+            Err(e) => format!("error: {e}"),
+        };
+        self.follow();
+    }
+
+    /// Put the cursor on the next command
+    fn follow(&mut self) {
+        if let Some(at) = self.session.position() {
+            self.cursor = at.offset;
+        }
+    }
+
+    /// Tell which command last wrote the current cell
+    fn last_write(&mut self) {
+        let pointer = self.session.tape().pointer();
+        self.status = match self.session.last_write(pointer) {
+            Some((step, at)) => format!(
+                "cell {pointer} last written by step {step} at line {}, column {}",
+                at.line, at.column
+            ),
+            None => format!("cell {pointer} not written in the history"),
+        };
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        if self.session.remove_breakpoint(self.cursor) {
+            self.status = "breakpoint removed".to_string();
+        } else if self.session.add_breakpoint(self.cursor).is_some() {
+            self.status = "breakpoint set".to_string();
+        }
+    }
+
+    /// Start over with the same input, keeping breakpoints and watchpoints
+    fn restart(&mut self) {
+        let input = self.session.input().get_ref().clone();
+        let mut session = Session::new(
+            self.session.source(),
+            self.session.tape().config(),
+            Cursor::new(input),
+            Vec::new(),
+        )
+        .expect("the source parsed before");
+        for at in self.session.breakpoints() {
+            session.add_breakpoint(at.offset);
+        }
+        for watchpoint in self.session.watchpoints() {
+            session.add_watchpoint(*watchpoint);
+        }
+        self.session = session;
+        self.status = "restarted".to_string();
+        self.follow();
+    }
+
+    /// Move the cursor by a number of commands
+    fn move_cursor(&mut self, by: isize) {
+        let commands = self.command_offsets();
+        let Some(index) = commands.iter().position(|&offset| offset == self.cursor) else {
+            return;
+        };
+        let index = index.saturating_add_signed(by).min(commands.len() - 1);
+        self.cursor = commands[index];
+    }
+
+    /// Move the cursor to the first command on the previous or next line
+    /// with any
+    fn move_line(&mut self, by: isize) {
+        let source = self.session.source();
+        let line = source[..self.cursor].matches('\n').count();
+        let lines: Vec<(usize, &str)> = source
+            .split_inclusive('\n')
+            .scan(0, |start, text| {
+                let line = (*start, text);
+                *start += text.len();
+                Some(line)
+            })
+            .collect();
+        let mut candidate = line;
+        while let Some(next) = candidate
+            .checked_add_signed(by)
+            .filter(|&l| l < lines.len())
+        {
+            candidate = next;
+            let (start, text) = lines[next];
+            if let Some(column) = text.find(|c| COMMANDS.contains(c)) {
+                self.cursor = start + column;
+                return;
+            }
+        }
+    }
+
+    fn command_offsets(&self) -> Vec<usize> {
+        self.session
+            .source()
+            .char_indices()
+            .filter(|(_, c)| COMMANDS.contains(*c))
+            .map(|(offset, _)| offset)
+            .collect()
+    }
+
+    fn render(&self, width: usize, height: usize) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let position = match self.session.position() {
+            Some(at) => format!("line {}, column {}", at.line, at.column),
+            None => "end".to_string(),
+        };
+        lines.push(vec![(
+            Style::Title,
+            format!(
+                "step {} | {position} | {}",
+                self.session.steps(),
+                self.status
+            ),
+        )]);
+        lines.extend(self.source_lines(height.saturating_sub(FIXED_LINES).max(1)));
+        lines.push(title("tape"));
+        lines.extend(self.tape_lines(width));
+        lines.push(title("input"));
+        let input = self.session.input();
+        let mut pending: Vec<u8> = self.session.replayed_input().collect();
+        pending.extend(&input.get_ref()[(input.position() as usize).min(input.get_ref().len())..]);
+        lines.push(vec![(Style::Plain, pending.escape_ascii().to_string())]);
+        lines.push(title("output"));
+        lines.extend(self.output_lines());
+        lines.push(vec![(Style::Dim, HELP.to_string())]);
+        for line in &mut lines {
+            truncate(line, width);
+        }
+        lines
+    }
+
+    /// The source around the cursor, numbered
+    fn source_lines(&self, height: usize) -> Vec<Line> {
+        let source = self.session.source();
+        let current = self.session.position().map(|at| at.offset);
+        let breakpoints: Vec<usize> = self
+            .session
+            .breakpoints()
+            .iter()
+            .map(|at| at.offset)
+            .collect();
+        let cursor_line = source[..self.cursor].matches('\n').count();
+        let first = cursor_line.saturating_sub(height / 2);
+
+        let mut start = 0;
+        let mut lines = Vec::new();
+        for (number, text) in source.split_inclusive('\n').enumerate() {
+            let end = start + text.len();
+            if (first..first + height).contains(&number) {
+                let mut line = vec![(Style::Dim, format!("{:>4} ", number + 1))];
+                for (offset, c) in text.char_indices() {
+                    let offset = start + offset;
+                    let style = if Some(offset) == current {
+                        Style::Current
+                    } else if offset == self.cursor {
+                        Style::Cursor
+                    } else if breakpoints.contains(&offset) {
+                        Style::Breakpoint
+                    } else {
+                        Style::Plain
+                    };
+                    let c = match c {
+                        '\n' | '\r' => continue,
+                        '\t' => ' ',
+                        c => c,
+                    };
+                    match line.last_mut() {
+                        Some((last, text)) if *last == style => text.push(c),
+                        _ => line.push((style, c.to_string())),
+                    }
+                }
+                lines.push(line);
+            }
+            start = end;
+        }
+        lines.resize(height, Vec::new());
+        lines
+    }
+
+    /// Positions and values of the cells around the pointer
+    fn tape_lines(&self, width: usize) -> Vec<Line> {
+        let tape = self.session.tape();
+        let count = (width / CELL_WIDTH).max(1) as isize;
+        let mut first = tape.pointer() - count / 2;
+        if tape.config().tape_length != TapeLength::Unbounded {
+            first = first.max(0);
+        }
+        let mut positions = Vec::new();
+        let mut values = Vec::new();
+        for position in first..first + count {
+            let style = if position == tape.pointer() {
+                Style::Current
+            } else {
+                Style::Plain
+            };
+            let value = tape.get_at(position - tape.pointer());
+            positions.push((Style::Dim, format!("{position:>CELL_WIDTH$}")));
+            values.push((Style::Plain, " ".to_string()));
+            values.push((style, format!("{value:>w$}", w = CELL_WIDTH - 1)));
+        }
+        vec![positions, values]
+    }
+
+    /// The last lines of output
+    fn output_lines(&self) -> Vec<Line> {
+        let output = &self.session.output()[..self.session.output_len()];
+        let output = String::from_utf8_lossy(output);
+        let lines: Vec<&str> = output.split('\n').collect();
+        let shown = &lines[lines.len().saturating_sub(OUTPUT_LINES)..];
+        let mut lines: Vec<Line> = shown
+            .iter()
+            .map(|line| {
+                let text: String = line
+                    .chars()
+                    .map(|c| if c.is_control() { '.' } else { c })
+                    .collect();
+                vec![(Style::Plain, text)]
+            })
+            .collect();
+        lines.resize(OUTPUT_LINES, Vec::new());
+        lines
+    }
+}
+
+fn title(name: &str) -> Line {
+    vec![(Style::Title, format!("-- {name} --"))]
+}
+
+/// Cut a line down to `width` characters
+fn truncate(line: &mut Line, width: usize) {
+    let mut left = width;
+    line.retain_mut(|(_, text)| {
+        if left == 0 {
+            return false;
+        }
+        if let Some((cut, _)) = text.char_indices().nth(left) {
+            text.truncate(cut);
+        }
+        left -= text.chars().count();
+        true
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MachineConfig;
+
+    fn app(source: &str, input: &[u8]) -> App {
+        let session = Session::new(
+            source,
+            &MachineConfig::default(),
+            Cursor::new(input.to_vec()),
+            Vec::new(),
+        )
+        .unwrap();
+        App::new(session)
+    }
+
+    /// A rendered line as plain text
+    fn text(line: &Line) -> String {
+        line.iter().map(|(_, text)| text.as_str()).collect()
+    }
+
+    /// Text of the first piece in the given style
+    fn styled(lines: &[Line], style: Style) -> Option<String> {
+        lines
+            .iter()
+            .flatten()
+            .find(|(s, _)| *s == style)
+            .map(|(_, text)| text.clone())
+    }
+
+    #[test]
+    fn test_render() {
+        // Every section is there and the screen is exactly as large as asked
+        let mut app = app("read ,\nprint [.-]", b"ab");
+        app.key(KeyCode::Char('s'));
+        let lines = app.render(40, 20);
+        assert_eq!(lines.len(), 20);
+        assert!(lines.iter().all(|line| text(line).chars().count() <= 40));
+        assert_eq!(text(&lines[0]), "step 1 | line 2, column 7 | stepped");
+        assert_eq!(text(&lines[2]), "   2 print [.-]");
+        assert_eq!(styled(&lines, Style::Current), Some("[".to_string()));
+        let tape = lines
+            .iter()
+            .position(|line| text(line) == "-- tape --")
+            .unwrap();
+        assert!(text(&lines[tape + 2]).starts_with("    97"));
+        let input = lines
+            .iter()
+            .position(|line| text(line) == "-- input --")
+            .unwrap();
+        assert_eq!(text(&lines[input + 1]), "b");
+    }
+
+    #[test]
+    fn test_keys() {
+        // Breakpoints go on the command under the cursor and stop `continue`
+        let mut app = app("+++[>+<-]\n>.", b"");
+        app.key(KeyCode::Down);
+        assert_eq!(app.cursor, 10);
+        app.key(KeyCode::Right);
+        app.key(KeyCode::Char('b'));
+        app.key(KeyCode::Char('c'));
+        assert_eq!(app.status, "breakpoint at line 2, column 2");
+        let lines = app.render(40, 20);
+        assert_eq!(styled(&lines, Style::Current), Some(".".to_string()));
+        app.key(KeyCode::Char('c'));
+        assert_eq!(app.status, "finished");
+        assert_eq!(text(&app.output_lines()[0]), ".");
+        app.key(KeyCode::Char('r'));
+        assert_eq!(app.session.steps(), 0);
+        assert_eq!(app.session.breakpoints().len(), 1);
+        assert!(!app.key(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn test_reverse_keys() {
+        // Going back gives input back and takes output away
+        let mut app = app(",.>+", b"a");
+        app.key(KeyCode::Char('c'));
+        app.key(KeyCode::Char('w'));
+        assert_eq!(
+            app.status,
+            "cell 1 last written by step 4 at line 1, column 4"
+        );
+        for _ in 0..4 {
+            app.key(KeyCode::Char('p'));
+        }
+        assert_eq!(text(&app.output_lines()[0]), "");
+        let lines = app.render(40, 20);
+        let input = lines
+            .iter()
+            .position(|line| text(line) == "-- input --")
+            .unwrap();
+        assert_eq!(text(&lines[input + 1]), "a");
+        app.key(KeyCode::Char('C'));
+        assert_eq!(app.status, "at the oldest recorded step");
+        app.key(KeyCode::Char('c'));
+        assert_eq!(text(&app.output_lines()[0]), "a");
+        assert_eq!(app.session.output(), b"a");
+    }
+
+    #[test]
+    fn test_truncate() {
+        // Lines are cut at a character boundary
+        let mut line = vec![
+            (Style::Plain, "äbc".to_string()),
+            (Style::Dim, "d".to_string()),
+        ];
+        truncate(&mut line, 2);
+        assert_eq!(line, vec![(Style::Plain, "äb".to_string())]);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        // A loop that never ends runs in parts until a key interrupts it,
+        // which does nothing else
+        let mut app = app("+[]", b"");
+        app.key(KeyCode::Char('c'));
+        assert_eq!(app.status, "running, any key interrupts");
+        app.tick();
+        assert!(app.session.steps() > CHUNK);
+        app.key(KeyCode::Char('s'));
+        assert_eq!(app.status, "interrupted");
+        assert!(app.running.is_none());
+        let steps = app.session.steps();
+        app.tick();
+        assert_eq!(app.session.steps(), steps);
+    }
+}
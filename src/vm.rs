@@ -0,0 +1,311 @@
+use std::io::{Read, Write};
+
+use crate::{
+    bytecode::{Bytecode, Instr},
+    interpreter::read_byte,
+    limits::Meter,
+    ExecError, Limits, MachineConfig, Tape,
+};
+
+/// Virtual machine running [`Bytecode`] in a single dispatch loop
+///
+/// It behaves exactly like the [`Interpreter`](crate::Interpreter) running
+/// the same lowered operations, just faster. Bytecode needs to be compiled
+/// for the machine it runs on, see [`Bytecode::compile`].
+pub struct Vm<R, W> {
+    tape: Tape,
+    input: R,
+    output: W,
+    meter: Meter,
+    /// The instruction the last run stopped at with an error
+    paused: Option<usize>,
+}
+
+impl<R: Read, W: Write> Vm<R, W> {
+    /// VM on a fresh default tape, reading from `input` and writing to `output`
+    pub fn new(input: R, output: W) -> Self {
+        Vm::with_config(&MachineConfig::default(), input, output)
+    }
+
+    /// VM on a fresh tape for the given machine model
+    pub fn with_config(config: &MachineConfig, input: R, output: W) -> Self {
+        Vm {
+            tape: Tape::new(config),
+            input,
+            output,
+            meter: Meter::default(),
+            paused: None,
+        }
+    }
+
+    /// The tape in its current state
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    /// Give back the tape, input and output
+    pub fn into_parts(self) -> (Tape, R, W) {
+        (self.tape, self.input, self.output)
+    }
+
+    /// Limits for running bytecode, the fuel being what is left of it
+    pub fn limits(&self) -> &Limits {
+        &self.meter.limits
+    }
+
+    /// Limit the instructions run and the growth of the tape
+    ///
+    /// Every step of an [`Instr::Scan`] costs fuel as well.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.tape.set_max_len(limits.max_tape_len);
+        self.meter = Meter::new(limits);
+    }
+
+    /// Give more fuel, e.g. to resume bytecode that ran out
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.meter.limits.add_fuel(fuel);
+    }
+
+    /// Run the bytecode and flush the output afterwards
+    pub fn run(&mut self, bytecode: &Bytecode) -> Result<(), ExecError> {
+        self.paused = None;
+        self.resume(bytecode)
+    }
+
+    /// Continue the bytecode the last run stopped in with an error, at the
+    /// instruction that failed, and flush the output afterwards
+    ///
+    /// The bytecode must be the same as in the last run; if that finished,
+    /// it runs from the start.
+    pub fn resume(&mut self, bytecode: &Bytecode) -> Result<(), ExecError> {
+        let mut pc = self.paused.take().unwrap_or(0);
+        let result = self.execute(bytecode.instructions(), &mut pc);
+        if result.is_err() {
+            self.paused = Some(pc);
+        }
+        self.output.flush()?;
+        result
+    }
+
+    /// Run from `pc` to the end, leaving `pc` at the instruction that failed
+    /// on an error
+    fn execute(&mut self, code: &[Instr], pc: &mut usize) -> Result<(), ExecError> {
+        while let Some(&instr) = code.get(*pc) {
+            self.meter.charge()?;
+            // An instruction that failed is paid for when it runs again
+            *pc = self.step(instr, *pc).inspect_err(|_| self.meter.refund())?;
+        }
+        Ok(())
+    }
+
+    /// Run the instruction at `pc`, giving back the next one to run
+    fn step(&mut self, instr: Instr, pc: usize) -> Result<usize, ExecError> {
+        let mut next = pc + 1;
+        match instr {
+            Instr::Add { offset, delta } => self.tape.add_at(offset, delta.into())?,
+            Instr::Move(distance) => self.tape.move_by(distance)?,
+            Instr::SetZero { offset } => self.tape.set_at(offset, 0)?,
+            Instr::AddMul { offset, factor } => self.tape.add_product(offset, factor)?,
+            Instr::Scan(step) => {
+                // Steps taken before an error are not taken again
+                while self.tape.get() != 0 {
+                    self.meter.charge()?;
+                    self.tape
+                        .move_by(step)
+                        .inspect_err(|_| self.meter.refund())?;
+                }
+            }
+            Instr::Output { offset } => self.output.write_all(&[self.tape.get_at(offset) as u8])?,
+            Instr::Input { offset } => {
+                let byte = read_byte(&mut self.input)?;
+                self.tape.store_input(offset, byte)?;
+            }
+            Instr::JumpIfZero(target) => {
+                if self.tape.get() == 0 {
+                    next = target;
+                }
+            }
+            Instr::JumpIfNotZero(target) => {
+                if self.tape.get() != 0 {
+                    next = target;
+                }
+            }
+        }
+        Ok(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, parse, CellWidth, EofBehavior, Interpreter, Overflow, TapeLength};
+    use std::time::Instant;
+
+    #[test]
+    fn test_vm_matches_interpreter() {
+        // Same output and tape as the reference interpreter on every machine
+        let programs = [
+            (include_str!("../programs/hello_world.bf"), &b""[..]),
+            (include_str!("../programs/rot13.bf"), b"Hello, World!"),
+            ("+++[>,[->+>+<<]>>[-<<+>>]<<.<-]", b"xy"),
+            ("<<+++[>>>+<<<-]>>>[<+>-]<.[<]-.", b""),
+        ];
+        let configs = [
+            MachineConfig::default(),
+            MachineConfig {
+                cell_width: CellWidth::I32,
+                eof: EofBehavior::Zero,
+                tape_length: TapeLength::Unbounded,
+                ..Default::default()
+            },
+            MachineConfig {
+                cell_width: CellWidth::U16,
+                overflow: Overflow::Saturate,
+                eof: EofBehavior::MinusOne,
+                ..Default::default()
+            },
+        ];
+        // ROT13 relies on `,` leaving the cell alone at the end of input
+        let runs = configs
+            .iter()
+            .flat_map(|config| programs.iter().map(move |program| (config, program)))
+            .filter(|(config, (source, _))| {
+                **config == MachineConfig::default() || !source.starts_with("ROT13")
+            });
+        for (config, &(source, input)) in runs {
+            let instructions = parse(source).unwrap();
+            let mut reference = Interpreter::with_config(config, input, Vec::new());
+            let expected_result = reference.run_instructions(&instructions);
+            let mut vm = Vm::with_config(config, input, Vec::new());
+            let result = vm.run(&Bytecode::from_ops(&ir::lower_for(&instructions, config)));
+            assert_eq!(result.is_ok(), expected_result.is_ok(), "{source}");
+            let (reference_tape, _, expected) = reference.into_parts();
+            let (tape, _, output) = vm.into_parts();
+            assert_eq!(output, expected, "{source}");
+            assert_eq!(tape, reference_tape, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_vm_overflow_matches_interpreter() {
+        // Cells that saturate or fail on overflow give the same results as
+        // the reference interpreter, also around runs like `+-`
+        let saturating = format!("{}++-.", "+".repeat(254));
+        let programs = [
+            (saturating.as_str(), &b""[..]),
+            ("-+.", b""),
+            (",[->+++<]>.", b"\xc8"),
+            ("+++[>-<-]>.", b""),
+            ("+[->++>+<-<]>.>.", b""),
+        ];
+        for overflow in [Overflow::Saturate, Overflow::Error] {
+            let config = MachineConfig {
+                overflow,
+                ..Default::default()
+            };
+            for &(source, input) in &programs {
+                let program = source.parse().unwrap();
+                let mut reference = Interpreter::with_config(&config, input, Vec::new());
+                let expected_result = reference.run(&program).map_err(|e| e.to_string());
+                let mut vm = Vm::with_config(&config, input, Vec::new());
+                let result = vm
+                    .run(&Bytecode::compile(&program, &config))
+                    .map_err(|e| e.to_string());
+                assert_eq!(result, expected_result, "{source}");
+                let (reference_tape, _, expected) = reference.into_parts();
+                let (tape, _, output) = vm.into_parts();
+                assert_eq!(output, expected, "{source}");
+                if result.is_ok() {
+                    assert_eq!(tape, reference_tape, "{source}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_vm_limits() {
+        // Each limit stops the VM, which goes on once the fuel is raised
+        let bytecode = Bytecode::compile(&"+[>+]".parse().unwrap(), &MachineConfig::default());
+        let config = MachineConfig {
+            tape_length: TapeLength::Growable,
+            ..Default::default()
+        };
+        let mut vm = Vm::with_config(&config, &b""[..], Vec::new());
+        vm.set_limits(Limits {
+            fuel: Some(10),
+            max_tape_len: Some(100),
+            ..Default::default()
+        });
+        assert!(matches!(vm.run(&bytecode), Err(ExecError::OutOfFuel)));
+        let pointer = vm.tape().pointer();
+        vm.add_fuel(1000);
+        assert!(matches!(
+            vm.resume(&bytecode),
+            Err(ExecError::TapeLimit { position: 100 })
+        ));
+        assert!(vm.tape().pointer() > pointer);
+        assert_eq!(vm.tape().cells()[1..], [1; 99]);
+
+        let mut vm = Vm::new(&b""[..], Vec::new());
+        vm.set_limits(Limits {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        });
+        assert!(matches!(
+            vm.run(&Bytecode::compile(
+                &"+[]".parse().unwrap(),
+                &MachineConfig::default()
+            )),
+            Err(ExecError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_vm_resume_fuel() {
+        // Instructions stopped by the tape limit, a scan among them, cost
+        // no more fuel in the end than running without the limit
+        let bytecode = Bytecode::compile(
+            &"+>+>+>+>+>+<<<<<[>]+".parse().unwrap(),
+            &MachineConfig::default(),
+        );
+        let config = MachineConfig {
+            tape_length: TapeLength::Growable,
+            ..Default::default()
+        };
+        let fuel = Some(1000);
+        let mut unlimited = Vm::with_config(&config, &b""[..], Vec::new());
+        unlimited.set_limits(Limits {
+            fuel,
+            ..Default::default()
+        });
+        unlimited.run(&bytecode).unwrap();
+
+        let mut vm = Vm::with_config(&config, &b""[..], Vec::new());
+        let mut limits = Limits {
+            fuel,
+            max_tape_len: Some(4),
+            ..Default::default()
+        };
+        vm.set_limits(limits);
+        assert!(matches!(
+            vm.run(&bytecode),
+            Err(ExecError::TapeLimit { .. })
+        ));
+        for max_tape_len in [Some(6), None] {
+            limits = Limits {
+                max_tape_len,
+                ..*vm.limits()
+            };
+            vm.set_limits(limits);
+            if max_tape_len.is_some() {
+                assert!(matches!(
+                    vm.resume(&bytecode),
+                    Err(ExecError::TapeLimit { position: 6 })
+                ));
+            }
+        }
+        vm.resume(&bytecode).unwrap();
+        assert_eq!(vm.limits().fuel, unlimited.limits().fuel);
+        assert_eq!(vm.tape(), unlimited.tape());
+    }
+}
@@ -0,0 +1,102 @@
+//! Assembles the generated x86-64 code with `as` and `ld` and compares the
+//! result with the reference interpreter. Skipped when binutils are missing
+//! or the host is not x86-64 Linux.
+
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+use brainfuck_parser::{codegen::asm, BrainfuckProgram, CellWidth, MachineConfig};
+use common::*;
+
+fn have_toolchain() -> bool {
+    let supported = cfg!(all(target_arch = "x86_64", target_os = "linux"));
+    if !(supported && have_tool("as") && have_tool("ld")) {
+        eprintln!("skipping, no x86-64 Linux toolchain found");
+        return false;
+    }
+    true
+}
+
+/// Assemble and link `name.s` in `dir`, returning the executable
+fn assemble(dir: &Path, name: &str) -> std::path::PathBuf {
+    let object = dir.join(format!("{name}.o"));
+    let executable = dir.join(name);
+    check(
+        Command::new("as")
+            .arg("-o")
+            .arg(&object)
+            .arg(dir.join(format!("{name}.s"))),
+    );
+    check(Command::new("ld").arg("-o").arg(&executable).arg(&object));
+    executable
+}
+
+#[test]
+fn test_asm_compile_command() {
+    // The whole way from a source file through the command line
+    if !have_toolchain() {
+        return;
+    }
+    let dir = scratch_dir("asm-cli");
+    let config = MachineConfig::default();
+    for (name, source, input) in PROGRAMS {
+        let bf_file = dir.join(format!("{name}.bf"));
+        fs::write(&bf_file, source).unwrap();
+        check(
+            Command::new(env!("CARGO_BIN_EXE_brainfuck-parser"))
+                .args(["compile", "--target", "x86_64-linux-asm", "-o"])
+                .arg(dir.join(format!("{name}.s")))
+                .arg(&bf_file),
+        );
+        let executable = assemble(&dir, name);
+        let output = run_executable(&mut Command::new(&executable), input);
+        assert_eq!(output, reference_output(&config, source, input), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_asm_wide_cells() {
+    if !have_toolchain() {
+        return;
+    }
+    let dir = scratch_dir("asm-wide");
+    for cell_width in [CellWidth::U16, CellWidth::I32] {
+        let config = MachineConfig {
+            cell_width,
+            ..Default::default()
+        };
+        for (name, source, input) in PROGRAMS {
+            let program: BrainfuckProgram = source.parse().unwrap();
+            fs::write(
+                dir.join(format!("{name}.s")),
+                asm::generate(&program, &config).unwrap(),
+            )
+            .unwrap();
+            let executable = assemble(&dir, name);
+            let output = run_executable(&mut Command::new(&executable), input);
+            assert_eq!(output, reference_output(&config, source, input), "{name}");
+        }
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_asm_off_tape() {
+    // Leaving the tape stops the program with an error
+    if !have_toolchain() {
+        return;
+    }
+    let dir = scratch_dir("asm-off-tape");
+    let program: BrainfuckProgram = "+[>+]".parse().unwrap();
+    fs::write(
+        dir.join("off_tape.s"),
+        asm::generate(&program, &MachineConfig::default()).unwrap(),
+    )
+    .unwrap();
+    let output = Command::new(assemble(&dir, "off_tape")).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stderr, b"data pointer moved off the tape\n");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,65 @@
+//! Compiles the generated C with the host compiler and compares the result
+//! with the reference interpreter. Skipped when no `cc` is installed.
+
+mod common;
+
+use std::{fs, process::Command};
+
+use brainfuck_parser::{
+    codegen::c, BrainfuckProgram, CellWidth, MachineConfig, Overflow, TapeLength,
+};
+use common::*;
+
+fn compile_and_run(config: &MachineConfig, test: &str, programs: &[(&str, &str, &[u8])]) {
+    if !have_tool("cc") {
+        eprintln!("skipping, no C compiler found");
+        return;
+    }
+    let dir = scratch_dir(test);
+    for &(name, source, input) in programs {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let c_file = dir.join(format!("{name}.c"));
+        let executable = dir.join(name);
+        fs::write(&c_file, c::generate(&program, config).unwrap()).unwrap();
+        check(
+            Command::new("cc")
+                .args(["-std=c99", "-O1", "-Wall", "-Werror", "-o"])
+                .arg(&executable)
+                .arg(&c_file),
+        );
+        let output = run_executable(&mut Command::new(&executable), input);
+        assert_eq!(output, reference_output(config, source, input), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_c_default_machine() {
+    compile_and_run(&MachineConfig::default(), "c-default", &PROGRAMS);
+}
+
+#[test]
+fn test_c_wide_cells_unbounded_tape() {
+    compile_and_run(
+        &MachineConfig {
+            cell_width: CellWidth::U16,
+            tape_length: TapeLength::Unbounded,
+            ..Default::default()
+        },
+        "c-wide",
+        &PROGRAMS,
+    );
+}
+
+#[test]
+fn test_c_saturating_cells() {
+    // Runs of `+`/`-` saturate in between, the samples rely on wrapping
+    compile_and_run(
+        &MachineConfig {
+            overflow: Overflow::Saturate,
+            ..Default::default()
+        },
+        "c-saturate",
+        &[OVERFLOW_RUNS],
+    );
+}
@@ -0,0 +1,156 @@
+//! Runs the command line tool on the sample programs
+
+mod common;
+
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+use brainfuck_parser::MachineConfig;
+use common::*;
+
+/// Run the tool with the given arguments and stdin
+fn cli(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_brainfuck-parser"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_run_engines() {
+    // Every engine prints what the reference interpreter prints, with the
+    // program from a file and its input from stdin
+    let dir = scratch_dir("cli-run");
+    for (name, source, input) in PROGRAMS {
+        let file = dir.join(format!("{name}.bf"));
+        fs::write(&file, source).unwrap();
+        let expected = reference_output(&MachineConfig::default(), source, input);
+        for engine in ["interpreter", "vm"] {
+            let output = cli(&["run", "--engine", engine, file.to_str().unwrap()], input);
+            assert!(output.status.success(), "{name} on {engine}");
+            assert_eq!(output.stdout, expected, "{name} on {engine}");
+        }
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_run_from_stdin() {
+    // A program from stdin reads its input from a file, or sees the end of
+    // input right away
+    let output = cli(&["run", "--eof=minus-one", "--cell-width", "16"], b"+,.");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [0xff]);
+    let dir = scratch_dir("cli-stdin");
+    let input = dir.join("input");
+    fs::write(&input, "ab").unwrap();
+    let output = cli(&["run", "--input", input.to_str().unwrap()], b",,.");
+    assert_eq!(output.stdout, b"b");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_errors() {
+    // Broken programs fail with 1 and point at the problem, a wrong command
+    // line fails with 2
+    let output = cli(&["check"], b"+\n+]");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: <stdin>: unmatched `]` at line 2, column 2\n+]\n ^\n"
+    );
+    let output = cli(&["run", "--tape", "1"], b">");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("off the tape"));
+    assert_eq!(cli(&["check", "--strict"], b"+ +").status.code(), Some(1));
+    for args in [
+        &["frobnicate"][..],
+        &[],
+        &["run", "--frobnicate"],
+        &["run", "--tape", "lots"],
+        &["compile"],
+        &["check", "a.bf", "b.bf"],
+    ] {
+        assert_eq!(cli(args, b"").status.code(), Some(2), "{args:?}");
+    }
+    assert!(cli(&["check", "--strict"], b"+[-]").status.success());
+}
+
+#[test]
+fn test_run_limits() {
+    // Programs that do not halt are stopped by any of the limits
+    for engine in ["interpreter", "vm"] {
+        let output = cli(&["run", "--engine", engine, "--fuel", "1000"], b"+[]");
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("out of fuel"));
+        let output = cli(&["run", "--engine", engine, "--timeout", "0.05"], b"+[]");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("deadline"));
+        let output = cli(
+            &[
+                "run",
+                "--engine",
+                engine,
+                "--tape=growable",
+                "--max-tape=64",
+            ],
+            b"+[>+]",
+        );
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cell 64"));
+    }
+    assert!(cli(&["run", "--fuel", "100"], b"+++[-]").status.success());
+    assert_eq!(cli(&["run", "--timeout", "-1"], b"").status.code(), Some(2));
+}
+
+#[test]
+fn test_fmt_and_minify() {
+    // Formatted code passes the check, minified code keeps only commands
+    let formatted = cli(&["fmt", "--width", "20"], PROGRAMS[0].1.as_bytes());
+    assert!(formatted.status.success());
+    assert!(cli(&["fmt", "--width=20", "--check"], &formatted.stdout)
+        .status
+        .success());
+    assert_eq!(
+        cli(&["fmt", "--check"], PROGRAMS[0].1.as_bytes())
+            .status
+            .code(),
+        Some(1)
+    );
+    let minified = cli(&["minify", "--peephole"], b"a +- [-] b +[-]");
+    assert_eq!(minified.stdout, b"+[-]");
+}
+
+#[test]
+fn test_stats_and_ast() {
+    // Sizes of the program and its instruction tree
+    let stats = cli(&["stats"], b"+[->+<] comment");
+    let stats = String::from_utf8(stats.stdout).unwrap();
+    assert!(stats.contains("source bytes         15\n"), "{stats}");
+    assert!(stats.contains("commands              7\n"), "{stats}");
+    assert!(stats.contains("loops                 1\n"), "{stats}");
+    let ast = cli(&["ast"], b"+.");
+    assert_eq!(ast.stdout, b"[\n    Increment,\n    Output,\n]\n");
+    let spans = cli(&["ast", "--spans"], b" +");
+    assert!(String::from_utf8_lossy(&spans.stdout).contains("column: 2"));
+}
+
+#[test]
+fn test_repl() {
+    // Lines share one tape and loops may span several lines
+    let output = cli(&["repl"], b"+++\n[->+\n<]>\n:ptr\n:quit\n+\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("bf> ... "), "{stdout}");
+    assert!(stdout.contains("cells 0 to 5: 0 [3] 0 0 0 0\n"), "{stdout}");
+    assert!(
+        stdout.ends_with("pointer at cell 1, holding 3\nbf> "),
+        "{stdout}"
+    );
+}
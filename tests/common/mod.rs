@@ -0,0 +1,88 @@
+//! Helpers shared by the backend integration tests
+
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use brainfuck_parser::{BrainfuckProgram, Interpreter, MachineConfig};
+
+/// Sample programs with the input they are run on
+pub const PROGRAMS: [(&str, &str, &[u8]); 4] = [
+    (
+        "hello_world",
+        include_str!("../../programs/hello_world.bf"),
+        b"",
+    ),
+    (
+        "rot13",
+        include_str!("../../programs/rot13.bf"),
+        b"Hello, World!",
+    ),
+    (
+        "nested_loops_reset",
+        include_str!("../../programs/nested_loops_reset.bf"),
+        b"",
+    ),
+    ("copy", "+++[>,[->+>+<<]>>[-<<+>>]<<.<-]>>>>[<]<<.", b"xy"),
+];
+
+/// A program printing 0 and 255 when wrapping, 1 and 254 on saturating
+/// 8-bit cells, for backends that fold runs of `+`/`-`
+pub const OVERFLOW_RUNS: (&str, &str, &[u8]) = (
+    "overflow_runs",
+    "-+.>+++++++++++++++[<+++++++++++++++++>-]<+-.",
+    b"",
+);
+
+/// Output of the reference interpreter
+pub fn reference_output(config: &MachineConfig, source: &str, input: &[u8]) -> Vec<u8> {
+    let program: BrainfuckProgram = source.parse().unwrap();
+    let mut interpreter = Interpreter::with_config(config, input, Vec::new());
+    interpreter.run(&program).unwrap();
+    interpreter.into_parts().2
+}
+
+/// Whether a tool can be found on the `PATH`
+pub fn have_tool(tool: &str) -> bool {
+    Command::new(tool)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// A fresh scratch directory for one test
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("brainfuck-parser-{test}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Run a command to completion, panicking with its stderr if it fails
+pub fn check(command: &mut Command) {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{command:?} failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Run an executable with the given input and collect its output
+pub fn run_executable(command: &mut Command, input: &[u8]) -> Vec<u8> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{command:?} failed");
+    output.stdout
+}
@@ -0,0 +1,90 @@
+//! Runs the directly written executables and compares the result with the
+//! reference interpreter. Skipped unless the host is x86-64 Linux.
+
+mod common;
+
+use std::{fs, process::Command};
+
+use brainfuck_parser::{codegen::elf, BrainfuckProgram, EofBehavior, MachineConfig};
+use common::*;
+
+fn supported() -> bool {
+    let supported = cfg!(all(target_arch = "x86_64", target_os = "linux"));
+    if !supported {
+        eprintln!("skipping, not on x86-64 Linux");
+    }
+    supported
+}
+
+#[test]
+fn test_elf_compile_command() {
+    // Compiled through the command line, which also makes the file executable
+    if !supported() {
+        return;
+    }
+    let dir = scratch_dir("elf-cli");
+    let config = MachineConfig::default();
+    for (name, source, input) in PROGRAMS {
+        let bf_file = dir.join(format!("{name}.bf"));
+        let executable = dir.join(name);
+        fs::write(&bf_file, source).unwrap();
+        check(
+            Command::new(env!("CARGO_BIN_EXE_brainfuck-parser"))
+                .args(["compile", "--target", "x86_64-linux-elf", "-o"])
+                .arg(&executable)
+                .arg(&bf_file),
+        );
+        let output = run_executable(&mut Command::new(&executable), input);
+        assert_eq!(output, reference_output(&config, source, input), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_elf_eof_behavior() {
+    // Reads past the end of the input and prints what ends up in the cell
+    if !supported() {
+        return;
+    }
+    let dir = scratch_dir("elf-eof");
+    let program: BrainfuckProgram = "+,.".parse().unwrap();
+    for (eof, expected) in [
+        (EofBehavior::Unchanged, 1),
+        (EofBehavior::Zero, 0),
+        (EofBehavior::MinusOne, 255),
+    ] {
+        let config = MachineConfig {
+            eof,
+            ..Default::default()
+        };
+        let executable = dir.join(format!("{eof:?}"));
+        fs::write(&executable, elf::generate(&program, &config).unwrap()).unwrap();
+        check(Command::new("chmod").arg("+x").arg(&executable));
+        assert_eq!(
+            run_executable(&mut Command::new(&executable), b""),
+            [expected]
+        );
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_elf_off_tape() {
+    // Leaving the tape stops the program with an error
+    if !supported() {
+        return;
+    }
+    let dir = scratch_dir("elf-off-tape");
+    let executable = dir.join("off_tape");
+    let program: BrainfuckProgram = "+[<+]".parse().unwrap();
+    fs::write(
+        &executable,
+        elf::generate(&program, &MachineConfig::default()).unwrap(),
+    )
+    .unwrap();
+    check(Command::new("chmod").arg("+x").arg(&executable));
+    let output = Command::new(&executable).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stderr, b"data pointer moved off the tape\n");
+    fs::remove_dir_all(dir).unwrap();
+}
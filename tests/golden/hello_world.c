@@ -0,0 +1,89 @@
+/* Generated by brainfuck-parser */
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef uint8_t cell;
+#define CELL_MIN 0
+#define CELL_MAX UINT8_MAX
+
+#define TAPE_LEN 30000L
+
+static cell tape[TAPE_LEN];
+static long p;
+
+/* The cell at position i, stopping if it is off the tape */
+static inline cell *at(long i) {
+    if (i < 0 || i >= TAPE_LEN) {
+        fprintf(stderr, "data pointer moved off the tape to cell %ld\n", i);
+        exit(1);
+    }
+    return &tape[i];
+}
+
+static inline void add(long i, long long delta) {
+    cell *c = at(i);
+    *c = (cell)((unsigned long long)*c + (unsigned long long)delta);
+}
+
+static inline void move(long distance) {
+    p += distance;
+    at(p);
+}
+
+static inline void output(long i) {
+    putchar((unsigned char)*at(i));
+}
+
+static inline void input(long i) {
+    int c = getchar();
+    if (c != EOF) {
+        *at(i) = (cell)c;
+    }
+}
+
+int main(void) {
+    add(p, 8);
+    while (*at(p)) {
+        add(p + 1, 4);
+        move(1);
+        add(p + 1, (long long)*at(p) * 2);
+        add(p + 2, (long long)*at(p) * 3);
+        add(p + 3, (long long)*at(p) * 3);
+        add(p + 4, (long long)*at(p) * 1);
+        *at(p) = 0;
+        add(p + 1, 1);
+        add(p + 2, 1);
+        add(p + 3, -1);
+        add(p + 5, 1);
+        move(5);
+        while (*at(p)) move(-1);
+        add(p - 1, -1);
+        move(-1);
+    }
+    output(p + 2);
+    add(p + 3, -3);
+    output(p + 3);
+    add(p + 3, 7);
+    output(p + 3);
+    output(p + 3);
+    add(p + 3, 3);
+    output(p + 3);
+    output(p + 5);
+    add(p + 4, -1);
+    output(p + 4);
+    output(p + 3);
+    add(p + 3, 3);
+    output(p + 3);
+    add(p + 3, -6);
+    output(p + 3);
+    add(p + 3, -8);
+    output(p + 3);
+    add(p + 5, 1);
+    output(p + 5);
+    add(p + 6, 2);
+    output(p + 6);
+    move(6);
+    return 0;
+}
@@ -0,0 +1,59 @@
+//! Runs the generated LLVM IR with `lli` and compares the result with the
+//! reference interpreter. Skipped when LLVM is not installed.
+
+mod common;
+
+use std::{fs, process::Command};
+
+use brainfuck_parser::{codegen::llvm, BrainfuckProgram, CellWidth, MachineConfig};
+use common::*;
+
+/// `lli`, with opaque pointers switched on for LLVM 14
+fn lli() -> Command {
+    let version = Command::new("lli").arg("--version").output().unwrap();
+    let version = String::from_utf8_lossy(&version.stdout);
+    let mut command = Command::new("lli");
+    if version.contains("LLVM version 14.") {
+        command.arg("-opaque-pointers");
+    }
+    command
+}
+
+fn compile_and_run(config: &MachineConfig, test: &str) {
+    if !have_tool("lli") {
+        eprintln!("skipping, no LLVM found");
+        return;
+    }
+    let dir = scratch_dir(test);
+    for (name, source, input) in PROGRAMS {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let ll_file = dir.join(format!("{name}.ll"));
+        fs::write(&ll_file, llvm::generate(&program, config).unwrap()).unwrap();
+        let output = run_executable(lli().arg(&ll_file), input);
+        assert_eq!(output, reference_output(config, source, input), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_llvm_default_machine() {
+    compile_and_run(&MachineConfig::default(), "llvm-default");
+}
+
+#[test]
+fn test_llvm_wide_cells() {
+    compile_and_run(
+        &MachineConfig {
+            cell_width: CellWidth::U16,
+            ..Default::default()
+        },
+        "llvm-u16",
+    );
+    compile_and_run(
+        &MachineConfig {
+            cell_width: CellWidth::I32,
+            ..Default::default()
+        },
+        "llvm-i32",
+    );
+}
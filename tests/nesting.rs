@@ -0,0 +1,71 @@
+//! Programs nested as deeply as the parser allows go through everything
+//! working on the instruction tree without running out of stack
+
+use brainfuck_parser::{
+    bytecode::Bytecode,
+    codegen::{asm, c, elf, llvm, rust, wasm},
+    debugger::Debugger,
+    format::{format, FormatOptions},
+    ir,
+    minify::{minify, MinifyOptions},
+    parse, parse_spanned,
+    passes::{OptLevel, Pipeline},
+    vm::Vm,
+    BrainfuckProgram, Interpreter, MachineConfig, ParseError, MAX_NESTING,
+};
+
+/// Loops nested `depth` deep, each running once one cell further right
+fn nested(depth: usize) -> String {
+    "+[>".repeat(depth) + &"<-]".repeat(depth)
+}
+
+#[test]
+fn test_too_deep() {
+    // One loop more than the limit fails to parse, pointing at its `[`
+    let source = format!(" {}", nested(MAX_NESTING + 1));
+    let err = parse(&source).unwrap_err();
+    assert!(matches!(err, ParseError::TooDeep { .. }));
+    assert_eq!(err.position().offset, 2 + 3 * MAX_NESTING);
+    assert!(parse_spanned(&source).is_err());
+    assert!(source.parse::<BrainfuckProgram>().is_err());
+}
+
+#[test]
+fn test_deepest_program() {
+    // Parsing, printing, optimizing, running and generating code all cope
+    // with the deepest program the parser accepts
+    let source = nested(MAX_NESTING);
+    let config = MachineConfig::default();
+    let program: BrainfuckProgram = source.parse().unwrap();
+    assert_eq!(program.depth(), MAX_NESTING);
+    assert_eq!(program.to_string(), source);
+    assert_eq!(program.clone(), program);
+    parse_spanned(&source).unwrap();
+    format(&program, &FormatOptions::default());
+    minify(&source, &MinifyOptions::default()).unwrap();
+
+    let mut interpreter = Interpreter::new(&b""[..], Vec::new());
+    interpreter.run(&program).unwrap();
+    let tape = interpreter.tape().clone();
+    let (ops, _) = Pipeline::preset(OptLevel::O2).optimize(&program);
+    let mut interpreter = Interpreter::new(&b""[..], Vec::new());
+    interpreter.run_ops(&ops).unwrap();
+    assert_eq!(interpreter.tape(), &tape);
+    let mut vm = Vm::new(&b""[..], Vec::new());
+    vm.run(&Bytecode::compile(&program, &config)).unwrap();
+    assert_eq!(vm.tape(), &tape);
+    let mut debugger = Debugger::new(&source, &config, &b""[..], Vec::new()).unwrap();
+    debugger.run().unwrap();
+    assert_eq!(debugger.tape(), &tape);
+    ir::lower(&program);
+
+    c::generate(&program, &config).unwrap();
+    rust::generate(&program, &config, rust::Entry::Main).unwrap();
+    wasm::generate_text(&program, &config).unwrap();
+    wasm::generate_binary(&program, &config).unwrap();
+    asm::generate(&program, &config).unwrap();
+    llvm::generate(&program, &config).unwrap();
+    elf::generate(&program, &config).unwrap();
+    #[cfg(feature = "jit")]
+    brainfuck_parser::jit::JitProgram::compile(&program, &config).unwrap();
+}
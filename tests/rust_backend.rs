@@ -0,0 +1,84 @@
+//! Compiles the generated Rust with rustc and compares the result with the
+//! reference interpreter. Skipped when no `rustc` is installed.
+
+mod common;
+
+use std::{fs, process::Command};
+
+use brainfuck_parser::{
+    codegen::rust::{self, Entry},
+    BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, Overflow, TapeLength,
+};
+use common::*;
+
+fn compile_and_run(config: &MachineConfig, test: &str, programs: &[(&str, &str, &[u8])]) {
+    if !have_tool("rustc") {
+        eprintln!("skipping, no Rust compiler found");
+        return;
+    }
+    let dir = scratch_dir(test);
+    for &(name, source, input) in programs {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let rs_file = dir.join(format!("{name}.rs"));
+        let executable = dir.join(name);
+        fs::write(
+            &rs_file,
+            rust::generate(&program, config, Entry::Main).unwrap(),
+        )
+        .unwrap();
+        check(
+            Command::new("rustc")
+                .args(["--edition=2021", "-D", "warnings", "-o"])
+                .arg(&executable)
+                .arg(&rs_file),
+        );
+        let output = run_executable(&mut Command::new(&executable), input);
+        assert_eq!(output, reference_output(config, source, input), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_rust_default_machine() {
+    compile_and_run(&MachineConfig::default(), "rust-default", &PROGRAMS);
+}
+
+#[test]
+fn test_rust_signed_cells_unbounded_tape() {
+    compile_and_run(
+        &MachineConfig {
+            cell_width: CellWidth::I32,
+            overflow: Overflow::Saturate,
+            tape_length: TapeLength::Unbounded,
+            eof: EofBehavior::Unchanged,
+        },
+        "rust-signed",
+        &PROGRAMS,
+    );
+}
+
+#[test]
+fn test_rust_wide_cells_growable_tape() {
+    compile_and_run(
+        &MachineConfig {
+            cell_width: CellWidth::U16,
+            tape_length: TapeLength::Growable,
+            ..Default::default()
+        },
+        "rust-growable",
+        &PROGRAMS,
+    );
+}
+
+#[test]
+fn test_rust_saturating_cells() {
+    // Runs of `+`/`-` saturate in between, the samples rely on wrapping
+    compile_and_run(
+        &MachineConfig {
+            overflow: Overflow::Saturate,
+            ..Default::default()
+        },
+        "rust-saturate",
+        &[OVERFLOW_RUNS],
+    );
+}
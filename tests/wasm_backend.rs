@@ -0,0 +1,99 @@
+//! Runs the generated WebAssembly with wasmi and compares the result with the
+//! reference interpreter
+
+mod common;
+
+use brainfuck_parser::{
+    codegen::wasm, BrainfuckProgram, CellWidth, EofBehavior, MachineConfig, TapeLength,
+};
+use common::*;
+use wasmi::{Caller, Engine, Linker, Module, Store};
+
+/// Remaining input and collected output of a running module
+struct Io {
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+/// Instantiate a binary module and call its `run` export
+fn run_module(bytes: &[u8], input: &[u8]) -> Result<Vec<u8>, wasmi::Error> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, bytes)?;
+    let mut store = Store::new(
+        &engine,
+        Io {
+            input: input.iter().rev().copied().collect(),
+            output: Vec::new(),
+        },
+    );
+    let mut linker = Linker::new(&engine);
+    linker.func_wrap("env", "read_byte", |mut caller: Caller<'_, Io>| {
+        caller.data_mut().input.pop().map_or(-1, i32::from)
+    })?;
+    linker.func_wrap(
+        "env",
+        "write_byte",
+        |mut caller: Caller<'_, Io>, byte: i32| caller.data_mut().output.push(byte as u8),
+    )?;
+    let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+    instance
+        .get_typed_func::<(), ()>(&store, "run")?
+        .call(&mut store, ())?;
+    Ok(store.into_data().output)
+}
+
+fn compare(config: &MachineConfig) {
+    for (name, source, input) in PROGRAMS {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let binary = wasm::generate_binary(&program, config).unwrap();
+        let text = wat::parse_str(wasm::generate_text(&program, config).unwrap()).unwrap();
+        let expected = reference_output(config, source, input);
+        assert_eq!(run_module(&binary, input).unwrap(), expected, "{name}");
+        assert_eq!(run_module(&text, input).unwrap(), expected, "{name}");
+    }
+}
+
+#[test]
+fn test_wasm_default_machine() {
+    compare(&MachineConfig::default());
+}
+
+#[test]
+fn test_wasm_wide_cells() {
+    compare(&MachineConfig {
+        cell_width: CellWidth::I32,
+        ..Default::default()
+    });
+}
+
+#[test]
+fn test_wasm_eof_behavior() {
+    // Reads past the end of the input and prints what ends up in the cell
+    let program: BrainfuckProgram = "+,.".parse().unwrap();
+    for (eof, expected) in [
+        (EofBehavior::Unchanged, 1),
+        (EofBehavior::Zero, 0),
+        (EofBehavior::MinusOne, 255),
+    ] {
+        let config = MachineConfig {
+            eof,
+            ..Default::default()
+        };
+        let binary = wasm::generate_binary(&program, &config).unwrap();
+        assert_eq!(run_module(&binary, b"").unwrap(), [expected]);
+    }
+}
+
+#[test]
+fn test_wasm_traps_off_tape() {
+    // Leaving the tape traps instead of touching memory past it
+    let config = MachineConfig {
+        tape_length: TapeLength::Fixed(4),
+        ..Default::default()
+    };
+    for source in ["<+", ">>>>+", "+[>+]"] {
+        let program: BrainfuckProgram = source.parse().unwrap();
+        let binary = wasm::generate_binary(&program, &config).unwrap();
+        assert!(run_module(&binary, b"").is_err(), "{source}");
+    }
+}